@@ -0,0 +1,85 @@
+//
+// embed.rs
+// Copyright (C) 2022 gmg137 <gmg137 AT live.com>
+// Distributed under terms of the GPL-3.0-or-later license.
+//
+//! Embeds the canvas in a larger window next to an outline of its items.
+//!
+//! Run with `cargo run --example embed`.
+use gtk::glib;
+use gtk::glib::clone;
+use gtk::prelude::*;
+use gtk::{Box, Label, ListBox, Orientation, Paned, ScrolledWindow, SelectionMode};
+use gtk4_dnd::{Canvas, PaletteBuilder};
+
+fn main() {
+    let application =
+        gtk::Application::new(Some("com.github.gmg137.gtk4-dnd.embed"), Default::default());
+    application.connect_startup(|_| gtk4_dnd::init());
+    application.connect_activate(build_ui);
+    application.run();
+}
+
+fn build_ui(application: &gtk::Application) {
+    let window = gtk::ApplicationWindow::new(application);
+    window.set_title(Some("Embedded canvas"));
+    window.set_default_size(900, 600);
+
+    let canvas = Canvas::builder()
+        .context_menu(false)
+        .history_limit(20)
+        .build();
+    canvas.new_item(60., 60.);
+
+    let palette = PaletteBuilder::new()
+        .colors(&["tomato", "gold", "yellowgreen", "skyblue", "plum"])
+        .styles(&["rainbow1"])
+        .orientation(Orientation::Vertical)
+        .build();
+
+    let outline = ListBox::new();
+    outline.set_selection_mode(SelectionMode::None);
+    let refresh = clone!(@weak outline => move |canvas: &Canvas| {
+        while let Some(row) = outline.first_child() {
+            outline.remove(&row);
+        }
+        for item in canvas.items().iter().rev() {
+            let label = Label::new(Some(&item.label()));
+            label.set_xalign(0.);
+            if item.is_selected() {
+                label.add_css_class("heading");
+            }
+            outline.append(&label);
+        }
+    });
+    refresh(&canvas);
+    for signal in [
+        "item-added",
+        "item-removed",
+        "item-changed",
+        "selection-changed",
+    ] {
+        canvas.connect_local(
+            signal,
+            false,
+            clone!(@strong refresh => move |values| {
+                refresh(&values[0].get::<Canvas>().unwrap());
+                None
+            }),
+        );
+    }
+
+    let sidebar = ScrolledWindow::new();
+    sidebar.set_child(Some(&outline));
+    sidebar.set_size_request(180, -1);
+
+    let content = Box::new(Orientation::Horizontal, 0);
+    content.append(&canvas);
+    content.append(&palette);
+
+    let paned = Paned::new(Orientation::Horizontal);
+    paned.set_start_child(&sidebar);
+    paned.set_end_child(&content);
+    window.set_child(Some(&paned));
+    window.show();
+}
@@ -0,0 +1,331 @@
+//
+// arrange.rs
+// Copyright (C) 2022 gmg137 <gmg137 AT live.com>
+// Distributed under terms of the GPL-3.0-or-later license.
+//
+use gtk::graphene::Rect;
+use gtk::prelude::*;
+use gtk::Orientation;
+
+use crate::canvas::Canvas;
+use crate::canvas_item::CanvasItem;
+use crate::document::ItemData;
+use crate::history::record_changes;
+
+/// Edge or centre line that `Canvas::align_selection` lines items up on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alignment {
+    Left,
+    Center,
+    Right,
+    Top,
+    Middle,
+    Bottom,
+}
+
+/// Moves the selected items so their rotated bounding boxes share the edge
+/// or centre line given by `alignment`, taken from the box around all of
+/// them. Locked items stay put but still count towards that box.
+pub fn align_selection(canvas: &Canvas, alignment: Alignment) {
+    let (items, boxes): (Vec<_>, Vec<_>) = selected_boxes(canvas).into_iter().unzip();
+    let moves = items
+        .into_iter()
+        .zip(align_shifts(&boxes, alignment))
+        .collect();
+    apply_moves(canvas, moves);
+}
+
+/// Spreads the selected items out so the gaps between their rotated
+/// bounding boxes are equal along `orientation`. The outermost items stay
+/// where they are.
+pub fn distribute_selection(canvas: &Canvas, orientation: Orientation) {
+    let (items, boxes): (Vec<_>, Vec<_>) = selected_boxes(canvas).into_iter().unzip();
+    let moves = items
+        .into_iter()
+        .zip(distribute_shifts(&boxes, orientation))
+        .collect();
+    apply_moves(canvas, moves);
+}
+
+/// How far each of `boxes` has to move to line up as `align_selection`
+/// describes. Empty for fewer than two boxes.
+fn align_shifts(boxes: &[Rect], alignment: Alignment) -> Vec<(f32, f32)> {
+    if boxes.len() < 2 {
+        return Vec::new();
+    }
+    let union = boxes[1..]
+        .iter()
+        .fold(boxes[0], |union, bounds| union.union(bounds));
+
+    boxes
+        .iter()
+        .map(|b| match alignment {
+            Alignment::Left => (union.x() - b.x(), 0.),
+            Alignment::Center => (mid_x(&union) - mid_x(b), 0.),
+            Alignment::Right => (right(&union) - right(b), 0.),
+            Alignment::Top => (0., union.y() - b.y()),
+            Alignment::Middle => (0., mid_y(&union) - mid_y(b)),
+            Alignment::Bottom => (0., bottom(&union) - bottom(b)),
+        })
+        .collect()
+}
+
+/// How far each of `boxes` has to move to be spread out as
+/// `distribute_selection` describes, taking them in the order of their
+/// centres. Empty for fewer than three boxes.
+fn distribute_shifts(boxes: &[Rect], orientation: Orientation) -> Vec<(f32, f32)> {
+    if boxes.len() < 3 {
+        return Vec::new();
+    }
+    let horizontal = orientation == Orientation::Horizontal;
+    let start = |b: &Rect| if horizontal { b.x() } else { b.y() };
+    let size = |b: &Rect| if horizontal { b.width() } else { b.height() };
+    let centre = |b: &Rect| start(b) + size(b) / 2.;
+    let mut order: Vec<usize> = (0..boxes.len()).collect();
+    order.sort_by(|&a, &b| centre(&boxes[a]).total_cmp(&centre(&boxes[b])));
+
+    let first = &boxes[order[0]];
+    let last = &boxes[order[order.len() - 1]];
+    let span = start(last) + size(last) - start(first);
+    let filled: f32 = boxes.iter().map(size).sum();
+    let gap = (span - filled) / (boxes.len() - 1) as f32;
+
+    let mut next = start(first);
+    let mut shifts = vec![(0., 0.); boxes.len()];
+    for i in order {
+        let shift = next - start(&boxes[i]);
+        next += size(&boxes[i]) + gap;
+        shifts[i] = if horizontal { (shift, 0.) } else { (0., shift) };
+    }
+    shifts
+}
+
+/// Direction `Canvas::restack_selection` moves the selected items in the
+/// stacking order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stacking {
+    /// On top of every other item.
+    Front,
+    /// One step up, past the next unselected item.
+    Raise,
+    /// One step down, past the next unselected item.
+    Lower,
+    /// Below every other item.
+    Back,
+}
+
+/// Moves the selected items up or down the stacking order, keeping their
+/// order relative to each other. Recorded as one undo step.
+pub fn restack_selection(canvas: &Canvas, stacking: Stacking) {
+    let mut order = canvas.items();
+    let selected: Vec<CanvasItem> = order.iter().filter(|i| i.is_selected()).cloned().collect();
+    if selected.is_empty() {
+        return;
+    }
+    restack(&mut order, CanvasItem::is_selected, stacking);
+    set_stacking_order(canvas, &order, &selected);
+}
+
+/// Moves the entries of `order`, given bottom to top, for which `selected`
+/// holds as `stacking` describes, keeping their order relative to each
+/// other.
+fn restack<T>(order: &mut Vec<T>, selected: impl Fn(&T) -> bool, stacking: Stacking) {
+    match stacking {
+        Stacking::Front | Stacking::Back => {
+            let (picked, rest): (Vec<T>, Vec<T>) = order.drain(..).partition(|t| selected(t));
+            if stacking == Stacking::Front {
+                order.extend(rest.into_iter().chain(picked));
+            } else {
+                order.extend(picked.into_iter().chain(rest));
+            }
+        }
+        Stacking::Raise => {
+            for i in (0..order.len().saturating_sub(1)).rev() {
+                if selected(&order[i]) && !selected(&order[i + 1]) {
+                    order.swap(i, i + 1);
+                }
+            }
+        }
+        Stacking::Lower => {
+            for i in 1..order.len() {
+                if selected(&order[i]) && !selected(&order[i - 1]) {
+                    order.swap(i, i - 1);
+                }
+            }
+        }
+    }
+}
+
+/// Reorders the items of `canvas` to match `order`, given bottom to top,
+/// and records the new places of `moved` as one undo step.
+pub(crate) fn set_stacking_order(canvas: &Canvas, order: &[CanvasItem], moved: &[CanvasItem]) {
+    let before: Vec<Option<ItemData>> = moved.iter().map(ItemData::capture).collect();
+    let mut previous: Option<CanvasItem> = None;
+    for item in order {
+        let next = match &previous {
+            Some(previous) => previous.next_sibling(),
+            None => canvas.first_child(),
+        };
+        if next.as_ref() != Some(item.upcast_ref()) {
+            item.insert_after(canvas, previous.as_ref());
+        }
+        previous = Some(item.clone());
+    }
+
+    // Record topmost first, as the history expects.
+    let mut changes: Vec<(CanvasItem, Option<ItemData>)> =
+        moved.iter().cloned().zip(before).collect();
+    changes.sort_by_key(|(item, _)| std::cmp::Reverse(order.iter().position(|i| i == item)));
+    let (items, before): (Vec<_>, Vec<_>) = changes.into_iter().unzip();
+    record_changes(canvas, &items, before, None);
+}
+
+/// The selected items with their bounds on the canvas, bottom to top.
+fn selected_boxes(canvas: &Canvas) -> Vec<(CanvasItem, Rect)> {
+    canvas
+        .selected_items()
+        .into_iter()
+        .filter_map(|item| {
+            let bounds = item.bounds(canvas)?;
+            Some((item, bounds))
+        })
+        .collect()
+}
+
+/// Moves each unlocked item by its shift and records the moves as one undo
+/// step.
+fn apply_moves(canvas: &Canvas, mut moves: Vec<(CanvasItem, (f32, f32))>) {
+    moves.retain(|(item, (dx, dy))| !item.is_locked() && (*dx != 0. || *dy != 0.));
+    let items: Vec<CanvasItem> = canvas
+        .items()
+        .into_iter()
+        .rev()
+        .filter(|item| moves.iter().any(|(moved, _)| moved == item))
+        .collect();
+    let before = items.iter().map(ItemData::capture).collect();
+    for (item, (dx, dy)) in &moves {
+        let (x, y) = canvas.child_position(item);
+        canvas.move_item(item, x + *dx as f64, y + *dy as f64);
+    }
+    record_changes(canvas, &items, before, None);
+}
+
+fn mid_x(rect: &Rect) -> f32 {
+    rect.x() + rect.width() / 2.
+}
+
+fn mid_y(rect: &Rect) -> f32 {
+    rect.y() + rect.height() / 2.
+}
+
+fn right(rect: &Rect) -> f32 {
+    rect.x() + rect.width()
+}
+
+fn bottom(rect: &Rect) -> f32 {
+    rect.y() + rect.height()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn boxes() -> Vec<Rect> {
+        vec![
+            Rect::new(10., 0., 20., 10.),
+            Rect::new(50., 30., 40., 20.),
+            Rect::new(0., 10., 10., 40.),
+        ]
+    }
+
+    #[test]
+    fn aligns_to_the_box_around_all() {
+        let boxes = boxes();
+        assert_eq!(
+            align_shifts(&boxes, Alignment::Left),
+            [(-10., 0.), (-50., 0.), (0., 0.)]
+        );
+        assert_eq!(
+            align_shifts(&boxes, Alignment::Right),
+            [(60., 0.), (0., 0.), (80., 0.)]
+        );
+        assert_eq!(
+            align_shifts(&boxes, Alignment::Center),
+            [(25., 0.), (-25., 0.), (40., 0.)]
+        );
+        assert_eq!(
+            align_shifts(&boxes, Alignment::Top),
+            [(0., 0.), (0., -30.), (0., -10.)]
+        );
+        assert_eq!(
+            align_shifts(&boxes, Alignment::Bottom),
+            [(0., 40.), (0., 0.), (0., 0.)]
+        );
+        assert_eq!(
+            align_shifts(&boxes, Alignment::Middle),
+            [(0., 20.), (0., -15.), (0., -5.)]
+        );
+    }
+
+    #[test]
+    fn needs_two_boxes_to_align() {
+        assert!(align_shifts(&boxes()[..1], Alignment::Left).is_empty());
+    }
+
+    #[test]
+    fn distributes_gaps_evenly_keeping_the_ends() {
+        // By their centres the boxes go 2, 0, 1 across 90 pixels, 70 of
+        // them filled, which leaves two gaps of 10.
+        let boxes = boxes();
+        assert_eq!(
+            distribute_shifts(&boxes, Orientation::Horizontal),
+            [(10., 0.), (0., 0.), (0., 0.)]
+        );
+    }
+
+    #[test]
+    fn distributes_vertically() {
+        let boxes = [
+            Rect::new(0., 0., 10., 10.),
+            Rect::new(0., 80., 10., 20.),
+            Rect::new(0., 20., 10., 10.),
+        ];
+        assert_eq!(
+            distribute_shifts(&boxes, Orientation::Vertical),
+            [(0., 0.), (0., 0.), (0., 20.)]
+        );
+    }
+
+    #[test]
+    fn needs_three_boxes_to_distribute() {
+        assert!(distribute_shifts(&boxes()[..2], Orientation::Horizontal).is_empty());
+    }
+
+    /// Restacks the letters of `order`, treating the capitals as selected.
+    fn restacked(order: &str, stacking: Stacking) -> String {
+        let mut order: Vec<char> = order.chars().collect();
+        restack(&mut order, char::is_ascii_uppercase, stacking);
+        order.into_iter().collect()
+    }
+
+    #[test]
+    fn restacks_to_the_ends() {
+        assert_eq!(restacked("aBcDe", Stacking::Front), "aceBD");
+        assert_eq!(restacked("aBcDe", Stacking::Back), "BDace");
+    }
+
+    #[test]
+    fn raises_and_lowers_one_step() {
+        assert_eq!(restacked("aBcDe", Stacking::Raise), "acBeD");
+        assert_eq!(restacked("aBcDe", Stacking::Lower), "BaDce");
+        assert_eq!(restacked("aBCd", Stacking::Raise), "adBC");
+        assert_eq!(restacked("aBCd", Stacking::Lower), "BCad");
+    }
+
+    #[test]
+    fn stops_at_the_ends() {
+        assert_eq!(restacked("abCD", Stacking::Raise), "abCD");
+        assert_eq!(restacked("ABcd", Stacking::Lower), "ABcd");
+        assert_eq!(restacked("abc", Stacking::Front), "abc");
+    }
+}
@@ -0,0 +1,953 @@
+//
+// canvas.rs
+// Copyright (C) 2022 gmg137 <gmg137 AT live.com>
+// Distributed under terms of the GPL-3.0-or-later license.
+//
+use glib::clone;
+use glib::subclass::Signal;
+use gtk::gdk::{Rectangle, BUTTON_PRIMARY, BUTTON_SECONDARY};
+use gtk::graphene::Rect;
+use gtk::prelude::*;
+use gtk::subclass::prelude::*;
+use gtk::{gdk, glib};
+use gtk::{
+    Box, Button, DragSource, DropControllerMotion, DropTarget, GestureClick, Grid, Label,
+    Orientation, PickFlags, Popover, PropagationPhase, Separator, Widget, WidgetPaintable,
+};
+use once_cell::sync::Lazy;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use crate::arrange::{
+    align_selection, distribute_selection, restack_selection, Alignment, Stacking,
+};
+use crate::canvas_item::CanvasItem;
+use crate::clipboard::{copy_selection, cut_selection, paste, track_pointer};
+use crate::document::{Document, ItemData};
+use crate::formats::{items_provider, items_taken, take_items};
+use crate::grid::{add_nudge_keys, draw_grid, snap_to_grid, snaps};
+use crate::guides::{draw_guides, set_guides, snap_drag};
+use crate::history::{record, record_changes, remove_items, Command, History, HISTORY_LIMIT};
+use crate::ids::IdAllocator;
+use crate::import::{import_document, import_files, import_texture};
+use crate::selection::{add_rubberband, select_for_action};
+use crate::view::CanvasView;
+
+/// Default distance between grid lines, and the smallest one allowed.
+pub const GRID_SIZE: f64 = 20.;
+const MIN_GRID_SIZE: f64 = 4.;
+
+glib::wrapper! {
+    pub struct Canvas(ObjectSubclass<ExCanvas>) @extends gtk::Fixed, gtk::Widget;
+}
+
+impl Default for Canvas {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Canvas {
+    pub fn new() -> Self {
+        glib::Object::new(&[]).expect("Failed to create Canvas")
+    }
+
+    pub fn builder() -> CanvasBuilder {
+        CanvasBuilder::default()
+    }
+
+    pub fn history(&self) -> Rc<RefCell<History>> {
+        self.imp().history.clone()
+    }
+
+    pub fn undo(&self) {
+        self.history().borrow_mut().undo(self);
+    }
+
+    pub fn redo(&self) {
+        self.history().borrow_mut().redo(self);
+    }
+
+    /// Puts the selected items on the clipboard. Returns false when nothing
+    /// is selected.
+    pub fn copy_selection(&self) -> bool {
+        copy_selection(self)
+    }
+
+    pub fn cut_selection(&self) {
+        cut_selection(self);
+    }
+
+    /// Pastes items copied from a canvas, or one item per line of text.
+    pub fn paste(&self) {
+        paste(self);
+    }
+
+    /// Whether double clicking an item or choosing Edit from its menu emits
+    /// `item-activated`, which a property editor can use to focus itself.
+    pub fn is_editable(&self) -> bool {
+        self.imp().editable.get()
+    }
+
+    pub fn set_editable(&self, editable: bool) {
+        self.imp().editable.set(editable);
+    }
+
+    /// Whether a right click opens the New/Edit/Duplicate/Delete menu.
+    pub fn has_context_menu(&self) -> bool {
+        self.imp().context_menu.get()
+    }
+
+    pub fn set_context_menu(&self, context_menu: bool) {
+        self.imp().context_menu.set(context_menu);
+    }
+
+    /// Whether dragging over empty space selects the items it touches.
+    pub fn has_rubberband(&self) -> bool {
+        self.imp().rubberband.get()
+    }
+
+    pub fn set_rubberband(&self, rubberband: bool) {
+        self.imp().rubberband.set(rubberband);
+    }
+
+    /// Whether files, images and items from other windows or applications
+    /// can be dropped. Items can always be dragged within the canvas.
+    pub fn accepts_external_drops(&self) -> bool {
+        self.imp().external_drops.get()
+    }
+
+    pub fn set_accepts_external_drops(&self, accepts: bool) {
+        self.imp().external_drops.set(accepts);
+    }
+
+    /// Whether clicking or dragging an item brings it to the top of the
+    /// stacking order.
+    pub fn has_auto_raise(&self) -> bool {
+        self.imp().auto_raise.get()
+    }
+
+    pub fn set_auto_raise(&self, auto_raise: bool) {
+        self.imp().auto_raise.set(auto_raise);
+    }
+
+    /// Whether a grid is drawn behind the items and drops and arrow key
+    /// nudges snap to it.
+    pub fn has_grid(&self) -> bool {
+        self.imp().grid.get()
+    }
+
+    pub fn set_grid(&self, grid: bool) {
+        self.imp().grid.set(grid);
+        self.queue_draw();
+    }
+
+    /// Distance between grid lines in pixels.
+    pub fn grid_size(&self) -> f64 {
+        self.imp().grid_size.get()
+    }
+
+    pub fn set_grid_size(&self, size: f64) {
+        self.imp().grid_size.set(size.max(MIN_GRID_SIZE));
+        self.queue_draw();
+    }
+
+    /// Rounds `(x, y)` to the nearest grid point, or returns it unchanged
+    /// while the grid is off.
+    pub fn snap(&self, x: f64, y: f64) -> (f64, f64) {
+        if !self.has_grid() {
+            return (x, y);
+        }
+        let size = self.grid_size();
+        (snap_to_grid(x, size), snap_to_grid(y, size))
+    }
+
+    /// Creates an item with the next default label and puts it on top of
+    /// the canvas at `(x, y)`.
+    pub fn new_item(&self, x: f64, y: f64) -> CanvasItem {
+        let item = CanvasItem::new();
+        item.set_label(&self.imp().ids.next_label());
+        self.add_item(&item, x, y);
+        item
+    }
+
+    /// Puts `item` on top of the canvas with its top-left corner at `(x, y)`.
+    /// The item keeps its id unless it has none or another item already
+    /// uses it.
+    pub fn add_item(&self, item: &CanvasItem, x: f64, y: f64) {
+        let id = item.id();
+        if id.is_empty() || self.item_by_id(&id).is_some() {
+            item.set_id(&self.imp().ids.next_id());
+        }
+        self.put(item, x, y);
+        item.apply_transform();
+        item.notify("x");
+        item.notify("y");
+        self.emit_by_name::<()>("item-added", &[item]);
+    }
+
+    pub fn remove_item(&self, item: &CanvasItem) {
+        let selected = item.is_selected();
+        self.remove(item);
+        self.emit_by_name::<()>("item-removed", &[item]);
+        if selected {
+            self.emit_by_name::<()>("selection-changed", &[]);
+        }
+    }
+
+    pub fn move_item(&self, item: &CanvasItem, x: f64, y: f64) {
+        let (old_x, old_y) = self.child_position(item);
+        if (old_x, old_y) != (x, y) {
+            self.move_(item, x, y);
+            if old_x != x {
+                item.notify("x");
+            }
+            if old_y != y {
+                item.notify("y");
+            }
+            self.emit_by_name::<()>("item-moved", &[item, &x, &y]);
+        }
+    }
+
+    /// Returns the items from bottom to top.
+    pub fn items(&self) -> Vec<CanvasItem> {
+        let mut items = Vec::new();
+        let mut child = self.first_child();
+        while let Some(widget) = child {
+            child = widget.next_sibling();
+            if let Ok(item) = widget.downcast::<CanvasItem>() {
+                items.push(item);
+            }
+        }
+        items
+    }
+
+    /// Returns the box around everything the items draw, in canvas
+    /// coordinates, or `None` while the canvas is empty.
+    pub fn items_bounds(&self) -> Option<Rect> {
+        self.items()
+            .iter()
+            .filter_map(|item| item.bounds(self))
+            .reduce(|union, bounds| union.union(&bounds))
+    }
+
+    /// Returns the part of the canvas on screen, in canvas coordinates.
+    /// Outside a [`CanvasView`] that is the whole allocation.
+    pub fn visible_area(&self) -> Rect {
+        self.imp()
+            .visible_area
+            .get()
+            .unwrap_or_else(|| Rect::new(0., 0., self.width() as f32, self.height() as f32))
+    }
+
+    /// Draws just the items, including those outside the allocation, which
+    /// a `WidgetPaintable` would clip.
+    pub(crate) fn snapshot_items(&self, snapshot: &gtk::Snapshot) {
+        self.imp().parent_snapshot(self, snapshot);
+    }
+
+    pub(crate) fn set_visible_area(&self, area: Option<Rect>) {
+        self.imp().visible_area.set(area);
+    }
+
+    pub fn item_by_id(&self, id: &str) -> Option<CanvasItem> {
+        self.items().into_iter().find(|item| item.id() == id)
+    }
+
+    /// Returns the selected items from bottom to top.
+    pub fn selected_items(&self) -> Vec<CanvasItem> {
+        self.items()
+            .into_iter()
+            .filter(CanvasItem::is_selected)
+            .collect()
+    }
+
+    pub fn select_only(&self, item: Option<&CanvasItem>) {
+        self.update_selection(|| {
+            for other in self.items() {
+                other.set_selected(Some(&other) == item);
+            }
+        });
+    }
+
+    /// Makes `items` the selection.
+    pub fn select_items(&self, items: &[CanvasItem]) {
+        self.update_selection(|| {
+            for other in self.items() {
+                other.set_selected(items.contains(&other));
+            }
+        });
+    }
+
+    pub fn set_item_selected(&self, item: &CanvasItem, selected: bool) {
+        self.update_selection(|| item.set_selected(selected));
+    }
+
+    /// Lines up two or more selected items on a shared edge or centre.
+    pub fn align_selection(&self, alignment: Alignment) {
+        align_selection(self, alignment);
+    }
+
+    /// Evens out the gaps between three or more selected items.
+    pub fn distribute_selection(&self, orientation: Orientation) {
+        distribute_selection(self, orientation);
+    }
+
+    /// Moves the selected items up or down the stacking order.
+    pub fn restack_selection(&self, stacking: Stacking) {
+        restack_selection(self, stacking);
+    }
+
+    /// Copies the selected items next to the originals.
+    pub fn duplicate_selection(&self) {
+        let document = Document::from_items(&self.selected_items());
+        let (left, top) = document.origin();
+        let copies = import_document(self, &document, left + 20., top + 20.);
+        self.select_items(&copies);
+    }
+
+    /// Tells listeners that the label, angle or style of `item` changed.
+    pub fn item_changed(&self, item: &CanvasItem) {
+        self.emit_by_name::<()>("item-changed", &[item]);
+    }
+
+    fn update_selection<F: FnOnce()>(&self, f: F) {
+        let before = self.selected_items();
+        f();
+        if self.selected_items() != before {
+            self.emit_by_name::<()>("selection-changed", &[]);
+        }
+    }
+}
+
+/// Configures a [`Canvas`]. Every behaviour except the grid is enabled by
+/// default.
+#[derive(Debug, Clone)]
+pub struct CanvasBuilder {
+    editable: bool,
+    context_menu: bool,
+    rubberband: bool,
+    external_drops: bool,
+    auto_raise: bool,
+    grid: bool,
+    grid_size: f64,
+    history_limit: usize,
+}
+
+impl Default for CanvasBuilder {
+    fn default() -> Self {
+        Self {
+            editable: true,
+            context_menu: true,
+            rubberband: true,
+            external_drops: true,
+            auto_raise: true,
+            grid: false,
+            grid_size: GRID_SIZE,
+            history_limit: HISTORY_LIMIT,
+        }
+    }
+}
+
+impl CanvasBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn editable(mut self, editable: bool) -> Self {
+        self.editable = editable;
+        self
+    }
+
+    pub fn context_menu(mut self, context_menu: bool) -> Self {
+        self.context_menu = context_menu;
+        self
+    }
+
+    pub fn rubberband(mut self, rubberband: bool) -> Self {
+        self.rubberband = rubberband;
+        self
+    }
+
+    pub fn external_drops(mut self, external_drops: bool) -> Self {
+        self.external_drops = external_drops;
+        self
+    }
+
+    pub fn auto_raise(mut self, auto_raise: bool) -> Self {
+        self.auto_raise = auto_raise;
+        self
+    }
+
+    pub fn grid(mut self, grid: bool) -> Self {
+        self.grid = grid;
+        self
+    }
+
+    pub fn grid_size(mut self, size: f64) -> Self {
+        self.grid_size = size;
+        self
+    }
+
+    /// Number of undo steps kept; 0 disables undo.
+    pub fn history_limit(mut self, limit: usize) -> Self {
+        self.history_limit = limit;
+        self
+    }
+
+    pub fn build(self) -> Canvas {
+        let canvas = Canvas::new();
+        canvas.set_editable(self.editable);
+        canvas.set_context_menu(self.context_menu);
+        canvas.set_rubberband(self.rubberband);
+        canvas.set_accepts_external_drops(self.external_drops);
+        canvas.set_auto_raise(self.auto_raise);
+        canvas.set_grid(self.grid);
+        canvas.set_grid_size(self.grid_size);
+        canvas.history().borrow_mut().set_limit(self.history_limit);
+        canvas
+    }
+}
+
+#[derive(Debug)]
+pub struct ExCanvas {
+    history: Rc<RefCell<History>>,
+    ids: IdAllocator,
+    editable: Cell<bool>,
+    context_menu: Cell<bool>,
+    rubberband: Cell<bool>,
+    external_drops: Cell<bool>,
+    auto_raise: Cell<bool>,
+    grid: Cell<bool>,
+    grid_size: Cell<f64>,
+    visible_area: Cell<Option<Rect>>,
+}
+
+impl Default for ExCanvas {
+    fn default() -> Self {
+        Self {
+            history: Rc::default(),
+            ids: IdAllocator::default(),
+            editable: Cell::new(true),
+            context_menu: Cell::new(true),
+            rubberband: Cell::new(true),
+            external_drops: Cell::new(true),
+            auto_raise: Cell::new(true),
+            grid: Cell::new(false),
+            grid_size: Cell::new(GRID_SIZE),
+            visible_area: Cell::new(None),
+        }
+    }
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for ExCanvas {
+    const NAME: &'static str = "Canvas";
+    type Type = Canvas;
+    type ParentType = gtk::Fixed;
+
+    fn class_init(klass: &mut Self::Class) {
+        klass.set_css_name("canvas");
+    }
+}
+
+impl ObjectImpl for ExCanvas {
+    fn constructed(&self, obj: &Self::Type) {
+        self.parent_constructed(obj);
+        obj.set_hexpand(true);
+        obj.set_vexpand(true);
+
+        add_drag_source(obj);
+        add_drop_target(obj);
+        add_click_gesture(obj);
+        add_rubberband(obj);
+        track_pointer(obj);
+        add_nudge_keys(obj);
+    }
+
+    fn signals() -> &'static [Signal] {
+        static SIGNALS: Lazy<Vec<Signal>> = Lazy::new(|| {
+            let item = CanvasItem::static_type().into();
+            vec![
+                Signal::builder("item-added", &[item], <()>::static_type().into()).build(),
+                Signal::builder("item-removed", &[item], <()>::static_type().into()).build(),
+                Signal::builder(
+                    "item-moved",
+                    &[item, f64::static_type().into(), f64::static_type().into()],
+                    <()>::static_type().into(),
+                )
+                .build(),
+                Signal::builder("item-changed", &[item], <()>::static_type().into()).build(),
+                Signal::builder("selection-changed", &[], <()>::static_type().into()).build(),
+                Signal::builder("item-activated", &[item], <()>::static_type().into()).build(),
+            ]
+        });
+        SIGNALS.as_ref()
+    }
+}
+
+impl WidgetImpl for ExCanvas {
+    // Inside a view the canvas extends past its allocation in every
+    // direction, so items at negative positions can still be picked.
+    fn contains(&self, widget: &Self::Type, x: f64, y: f64) -> bool {
+        widget
+            .parent()
+            .is_some_and(|parent| parent.is::<CanvasView>())
+            || self.parent_contains(widget, x, y)
+    }
+
+    fn snapshot(&self, widget: &Self::Type, snapshot: &gtk::Snapshot) {
+        draw_grid(widget, snapshot);
+        self.parent_snapshot(widget, snapshot);
+        draw_guides(widget, snapshot);
+    }
+}
+impl FixedImpl for ExCanvas {}
+
+fn add_drag_source(canvas: &Canvas) {
+    let source = DragSource::new();
+    // Copy by default so items can be dragged to other windows and
+    // applications; Shift turns the drag into a move.
+    source.set_actions(gdk::DragAction::COPY | gdk::DragAction::MOVE);
+    source.connect_prepare(
+        clone!(@weak canvas => @default-return None, move |_source, x, y| {
+            let picked = canvas.pick(x, y, PickFlags::DEFAULT).unwrap();
+            let item: CanvasItem = picked.ancestor(CanvasItem::static_type())?.downcast().unwrap();
+            if item.is_locked() || picked.has_css_class("handle") {
+                return None;
+            }
+            let items = select_for_action(&canvas, &item);
+            unsafe {
+                canvas.set_data::<Widget>("dragged-item", item.clone().upcast());
+                canvas.set_data("dragged-group", items.clone());
+            }
+            Some(items_provider(&item, &items))
+        }),
+    );
+    source.connect_drag_begin(clone!(@weak canvas => move |source, _drag| {
+        unsafe {
+            let item = canvas.data::<Widget>("dragged-item").unwrap();
+            let item: &CanvasItem = item.as_ref().downcast_ref().unwrap();
+            let paintable = WidgetPaintable::new(Some(item));
+            source.set_icon(Some(&paintable), item.r(), item.r());
+            item.add_css_class("dragging");
+            for item in canvas.data::<Vec<CanvasItem>>("dragged-group").unwrap().as_ref() {
+                item.add_css_class("dragging");
+            }
+        }
+    }));
+    source.connect_drag_end(
+        clone!(@weak canvas => move |_source, drag, delete_data| unsafe {
+            if let Some(item) = canvas.steal_data::<Widget>("dragged-item") {
+                item.remove_css_class("dragging");
+            }
+            let dropped_here = canvas.steal_data::<bool>("local-drop").is_some();
+            let mut group = canvas
+                .steal_data::<Vec<CanvasItem>>("dragged-group")
+                .unwrap_or_default();
+            stop_autoscroll(&canvas);
+            set_guides(&canvas, Vec::new());
+            for item in &group {
+                item.remove_css_class("dragging");
+            }
+
+            // The items were moved to another canvas. Other applications
+            // only get a copy, whatever action they picked.
+            if delete_data && !dropped_here && items_taken(&drag.content()) {
+                group.retain(|item| item.parent().as_ref() == Some(canvas.upcast_ref()));
+                remove_items(&canvas, &group);
+            }
+        }),
+    );
+    source.connect_drag_cancel(|_, _, _| false);
+    canvas.add_controller(&source);
+}
+
+fn add_drop_target(canvas: &Canvas) {
+    let dest = DropTarget::new(
+        glib::Type::INVALID,
+        gdk::DragAction::MOVE | gdk::DragAction::COPY,
+    );
+    dest.set_types(&[
+        Widget::static_type(),
+        Document::static_type(),
+        gdk::FileList::static_type(),
+        gdk::Texture::static_type(),
+    ]);
+    dest.connect_enter(|target, _, _| drop_action(target));
+    dest.connect_motion(|target, x, y| {
+        let canvas: Canvas = target.widget().downcast().unwrap();
+        autoscroll(&canvas, x, y);
+        update_guides(target, x, y);
+        drop_action(target)
+    });
+    dest.connect_leave(|target| {
+        let canvas: Canvas = target.widget().downcast().unwrap();
+        stop_autoscroll(&canvas);
+        set_guides(&canvas, Vec::new());
+    });
+    dest.connect_drop(|target, value, x, y| {
+        let canvas: Canvas = target.widget().downcast().unwrap();
+        stop_autoscroll(&canvas);
+        set_guides(&canvas, Vec::new());
+        if drop_action(target).is_empty() {
+            return false;
+        }
+        let state = target.current_event_state();
+        let (drop_x, drop_y) = (x, y);
+        let (x, y) = if snaps(&canvas, state) {
+            canvas.snap(x, y)
+        } else {
+            (x, y)
+        };
+        if let Ok(files) = value.get::<gdk::FileList>() {
+            return import_files(&canvas, &files.files(), x, y);
+        }
+        if let Ok(texture) = value.get::<gdk::Texture>() {
+            return import_texture(&canvas, &texture, x, y);
+        }
+        if let Ok(document) = value.get::<Document>() {
+            return !import_document(&canvas, &document, x, y).is_empty();
+        }
+
+        let item = value.get::<Widget>().unwrap();
+        let item: CanvasItem = item.downcast().unwrap();
+        // The item may have been removed, for instance undone, during the drag.
+        let source = match item
+            .parent()
+            .and_then(|parent| parent.downcast::<Canvas>().ok())
+        {
+            Some(source) => source,
+            None => return false,
+        };
+        let (x, y) = (drop_x - item.r() as f64, drop_y - item.r() as f64);
+        let (x, y, _) = snap_drag(&canvas, &item, x, y, state);
+        if source != canvas {
+            let drag = target.current_drop().and_then(|drop| drop.drag());
+            if let Some(drag) = drag {
+                take_items(&drag.content());
+            }
+            return copy_dropped_items(&canvas, &source, &item, x, y);
+        }
+        unsafe {
+            canvas.set_data("local-drop", true);
+        }
+        if drop_action(target) == gdk::DragAction::COPY {
+            return copy_dropped_items(&canvas, &source, &item, x, y);
+        }
+
+        // Move the whole selection along with the dragged item, keeping the
+        // offsets between the items and their relative stacking order.
+        let mut group = vec![item.clone()];
+        if item.is_selected() {
+            group = canvas.selected_items();
+            group.retain(|item| !item.is_locked());
+        }
+        group.reverse();
+        let before = group.iter().map(ItemData::capture).collect();
+
+        let (old_x, old_y) = canvas.child_position(&item);
+        let (dx, dy) = (x - old_x, y - old_y);
+        for item in group.iter().rev() {
+            let (x, y) = canvas.child_position(item);
+            canvas.move_item(item, x + dx, y + dy);
+            if canvas.has_auto_raise() {
+                item.insert_before(&canvas, None::<&Widget>);
+            }
+        }
+        record_changes(&canvas, &group, before, None);
+        true
+    });
+    canvas.add_controller(&dest);
+
+    // The target above only sees drags it accepts, and none while an item
+    // takes the drop, such as a swatch over an item. Watching every drag
+    // in the capture phase keeps the view scrolling for those too.
+    let motion = DropControllerMotion::new();
+    motion.set_propagation_phase(PropagationPhase::Capture);
+    motion.connect_enter(|motion, x, y| {
+        let canvas: Canvas = motion.widget().downcast().unwrap();
+        autoscroll(&canvas, x, y);
+    });
+    motion.connect_motion(|motion, x, y| {
+        let canvas: Canvas = motion.widget().downcast().unwrap();
+        autoscroll(&canvas, x, y);
+    });
+    motion.connect_leave(|motion| {
+        let canvas: Canvas = motion.widget().downcast().unwrap();
+        stop_autoscroll(&canvas);
+    });
+    canvas.add_controller(&motion);
+}
+
+/// Scrolls the view showing `canvas`, if any, while a drag at `(x, y)` is
+/// near its edges.
+fn autoscroll(canvas: &Canvas, x: f64, y: f64) {
+    let view = canvas
+        .parent()
+        .and_then(|p| p.downcast::<CanvasView>().ok());
+    if let Some(view) = view {
+        if let Some((x, y)) = canvas.translate_coordinates(&view, x, y) {
+            view.autoscroll(x, y);
+        }
+    }
+}
+
+fn stop_autoscroll(canvas: &Canvas) {
+    let view = canvas
+        .parent()
+        .and_then(|p| p.downcast::<CanvasView>().ok());
+    if let Some(view) = view {
+        view.stop_autoscroll();
+    }
+}
+
+/// Shows where an item dragged within the canvas would line up with the
+/// other items if dropped with the pointer at `(x, y)`.
+fn update_guides(target: &DropTarget, x: f64, y: f64) {
+    let canvas: Canvas = target.widget().downcast().unwrap();
+    let item = unsafe {
+        canvas
+            .data::<Widget>("dragged-item")
+            .map(|item| item.as_ref().clone())
+    };
+    let guides = match item.and_then(|item| item.downcast::<CanvasItem>().ok()) {
+        Some(item) => {
+            let (x, y) = (x - item.r() as f64, y - item.r() as f64);
+            snap_drag(&canvas, &item, x, y, target.current_event_state()).2
+        }
+        None => Vec::new(),
+    };
+    set_guides(&canvas, guides);
+}
+
+/// Moving is the default within one canvas; Ctrl limits the drag to `COPY`
+/// and duplicates the items. Everything else is copied unless the source
+/// only allows moving.
+fn drop_action(target: &DropTarget) -> gdk::DragAction {
+    let canvas = target.widget();
+    let actions = match target.current_drop() {
+        Some(drop) => drop.actions(),
+        None => return gdk::DragAction::empty(),
+    };
+    let local = unsafe { canvas.data::<Widget>("dragged-item").is_some() };
+    let accepts = canvas
+        .downcast_ref::<Canvas>()
+        .is_none_or(Canvas::accepts_external_drops);
+    if !local && !accepts {
+        gdk::DragAction::empty()
+    } else if (local && actions.contains(gdk::DragAction::MOVE))
+        || !actions.contains(gdk::DragAction::COPY)
+    {
+        gdk::DragAction::MOVE
+    } else {
+        gdk::DragAction::COPY
+    }
+}
+
+/// Recreates `item`, and the items dragged with it from `source`, with the
+/// copy of the dragged item at `(x, y)`. Used for drags from the canvas of
+/// another window and for Ctrl-drags within one canvas. The copies become the
+/// selection.
+fn copy_dropped_items(canvas: &Canvas, source: &Canvas, item: &CanvasItem, x: f64, y: f64) -> bool {
+    let items = unsafe { source.data::<Vec<CanvasItem>>("dragged-group") }.map_or_else(
+        || vec![item.clone()],
+        |group| unsafe { group.as_ref().clone() },
+    );
+    let document = Document::from_items(&items);
+
+    let (item_x, item_y) = source.child_position(item);
+    let (left, top) = document.origin();
+    let x = x + left - item_x;
+    let y = y + top - item_y;
+    let copies = import_document(canvas, &document, x, y);
+    canvas.select_items(&copies);
+    !copies.is_empty()
+}
+
+fn add_click_gesture(canvas: &Canvas) {
+    let gesture = GestureClick::new();
+    gesture.set_button(0);
+    gesture.connect_pressed(clone!(@weak canvas =>move |gesture, _n_press, x, y| {
+        canvas.grab_focus();
+        let widget = gesture.widget();
+        let child = widget.pick(x, y, PickFlags::DEFAULT).unwrap();
+        let child = child.ancestor(child.type_()).unwrap();
+
+        if gesture.current_button() == BUTTON_SECONDARY && canvas.has_context_menu() {
+            if let Some(item) = child.ancestor(CanvasItem::static_type()) {
+                select_for_action(&canvas, item.downcast_ref().unwrap());
+            }
+
+            let menu = Popover::new();
+            menu.set_parent(&widget);
+            menu.set_has_arrow(false);
+            // A new menu is built for every click; drop it once it closes,
+            // after the click that closed it is handled.
+            menu.connect_closed(|menu| {
+                glib::idle_add_local_once(clone!(@weak menu => move || menu.unparent()));
+            });
+            let rect = Rectangle::new(x as i32, y as i32, 1, 1);
+            menu.set_pointing_to(Some(&rect));
+            let box1 = Box::new(Orientation::Vertical, 0);
+            menu.set_child(Some(&box1));
+
+            let item = Button::with_label("New");
+            item.set_has_frame(false);
+            item.connect_clicked(clone!(@weak canvas =>move |button| {
+                let popover = button.ancestor(Popover::static_type()).unwrap();
+                let popover: Popover = popover.downcast().unwrap();
+                let rect = popover.pointing_to().1;
+
+                let item = canvas.new_item(rect.x() as f64, rect.y() as f64);
+                if let Some(data) = ItemData::capture(&item) {
+                    record(&canvas, Command::Add(data), None);
+                }
+
+                popover.popdown();
+            }));
+            box1.append(&item);
+
+            let item = Separator::new(Orientation::Horizontal);
+            box1.append(&item);
+
+            let item = Button::with_label("Edit");
+            item.set_has_frame(false);
+            item.set_sensitive(child != widget);
+            item.set_visible(canvas.is_editable());
+            item.connect_clicked(clone!(@weak canvas => move|button|{
+                let popover = button.ancestor(Popover::static_type()).unwrap();
+                let popover: Popover = popover.downcast().unwrap();
+                popover.popdown();
+
+                if let Some(item) = canvas.selected_items().last() {
+                    canvas.emit_by_name::<()>("item-activated", &[item]);
+                }
+            }));
+            box1.append(&item);
+
+            let item = Button::with_label("Duplicate");
+            item.set_has_frame(false);
+            item.set_sensitive(child != widget);
+            item.connect_clicked(clone!(@weak canvas => move |button| {
+                let popover = button.ancestor(Popover::static_type()).unwrap();
+                let popover: Popover = popover.downcast().unwrap();
+                popover.popdown();
+
+                canvas.duplicate_selection();
+            }));
+            box1.append(&item);
+
+            let selected = canvas.selected_items().len();
+            let item = Separator::new(Orientation::Horizontal);
+            box1.append(&item);
+            let item = Label::new(Some("Align"));
+            item.add_css_class("dim-label");
+            box1.append(&item);
+            let grid = Grid::new();
+            grid.set_column_homogeneous(true);
+            let alignments = [
+                ("Left", Alignment::Left),
+                ("Center", Alignment::Center),
+                ("Right", Alignment::Right),
+                ("Top", Alignment::Top),
+                ("Middle", Alignment::Middle),
+                ("Bottom", Alignment::Bottom),
+            ];
+            for (i, (label, alignment)) in alignments.into_iter().enumerate() {
+                let item = Button::with_label(label);
+                item.set_has_frame(false);
+                item.set_sensitive(selected >= 2);
+                item.connect_clicked(clone!(@weak canvas => move |button| {
+                    let popover = button.ancestor(Popover::static_type()).unwrap();
+                    let popover: Popover = popover.downcast().unwrap();
+                    popover.popdown();
+
+                    canvas.align_selection(alignment);
+                }));
+                grid.attach(&item, i as i32 % 3, i as i32 / 3, 1, 1);
+            }
+            box1.append(&grid);
+
+            for (label, orientation) in [
+                ("Distribute Horizontally", Orientation::Horizontal),
+                ("Distribute Vertically", Orientation::Vertical),
+            ] {
+                let item = Button::with_label(label);
+                item.set_has_frame(false);
+                item.set_sensitive(selected >= 3);
+                item.connect_clicked(clone!(@weak canvas => move |button| {
+                    let popover = button.ancestor(Popover::static_type()).unwrap();
+                    let popover: Popover = popover.downcast().unwrap();
+                    popover.popdown();
+
+                    canvas.distribute_selection(orientation);
+                }));
+                box1.append(&item);
+            }
+
+            let item = Separator::new(Orientation::Horizontal);
+            box1.append(&item);
+
+            for (label, stacking) in [
+                ("Bring to Front", Stacking::Front),
+                ("Raise", Stacking::Raise),
+                ("Lower", Stacking::Lower),
+                ("Send to Back", Stacking::Back),
+            ] {
+                let item = Button::with_label(label);
+                item.set_has_frame(false);
+                item.set_sensitive(selected >= 1);
+                item.connect_clicked(clone!(@weak canvas => move |button| {
+                    let popover = button.ancestor(Popover::static_type()).unwrap();
+                    let popover: Popover = popover.downcast().unwrap();
+                    popover.popdown();
+
+                    canvas.restack_selection(stacking);
+                }));
+                box1.append(&item);
+            }
+
+            let item = Separator::new(Orientation::Horizontal);
+            box1.append(&item);
+
+            let item = Button::with_label("Delete");
+            item.set_has_frame(false);
+            item.set_sensitive(child != widget);
+            item.connect_clicked(clone!(@weak canvas => move|button|{
+                remove_items(&canvas, &canvas.selected_items());
+                let popover = button.ancestor(Popover::static_type()).unwrap();
+                let popover: Popover = popover.downcast().unwrap();
+                popover.popdown();
+            }));
+            box1.append(&item);
+
+            menu.popup();
+        }
+    }));
+
+    gesture.connect_released(clone!(@weak canvas => move |gesture, n_press, x, y| {
+        if gesture.current_button() != BUTTON_PRIMARY {
+            return;
+        }
+        let widget = gesture.widget();
+        let child = widget.pick(x, y, PickFlags::DEFAULT).unwrap();
+        let state = gesture.current_event_state();
+        if let Some(item) = child.ancestor(CanvasItem::static_type()) {
+            let item: CanvasItem = item.downcast().unwrap();
+            if state.contains(gdk::ModifierType::SHIFT_MASK) {
+                canvas.set_item_selected(&item, true);
+            } else if state.contains(gdk::ModifierType::CONTROL_MASK) {
+                canvas.set_item_selected(&item, !item.is_selected());
+            } else {
+                canvas.select_only(Some(&item));
+                if n_press == 2 && canvas.is_editable() {
+                    canvas.emit_by_name::<()>("item-activated", &[&item]);
+                }
+            }
+        } else if child == widget {
+            canvas.select_only(None);
+        }
+    }));
+    canvas.add_controller(&gesture);
+}
@@ -5,27 +5,69 @@
 //
 
 use gtk::gdk::DragAction;
+use gtk::gdk::ModifierType;
+use gtk::gdk::Texture;
 use gtk::gdk::RGBA;
 use gtk::glib;
 use gtk::glib::clone;
 use gtk::glib::Type;
+use gtk::glib::{
+    ParamFlags, ParamSpec, ParamSpecBoolean, ParamSpecBoxed, ParamSpecDouble, ParamSpecFloat,
+    ParamSpecString,
+};
+use gtk::graphene::{Point, Rect};
+use gtk::gsk::Transform;
 use gtk::prelude::*;
 use gtk::subclass::prelude::*;
 use gtk::Box;
 use gtk::CssProvider;
 use gtk::DropTarget;
-use gtk::Entry;
+use gtk::EventSequenceState;
 use gtk::Fixed;
 use gtk::GestureClick;
+use gtk::GestureDrag;
 use gtk::GestureRotate;
 use gtk::Label;
 use gtk::Orientation;
-use gtk::Scale;
+use gtk::Picture;
 use gtk::Settings;
-use std::cell::RefCell;
+use once_cell::sync::Lazy;
+use std::cell::{Cell, RefCell};
 use std::f64::consts::PI;
+use std::time::Duration;
 
-use crate::ITEM_ID;
+use crate::canvas::Canvas;
+use crate::document::ItemData;
+use crate::formats::{is_valid_class_name, parse_hex_color, Style, ITEMS_MIME_TYPE};
+use crate::history;
+
+/// Largest width or height an image is shown at inside an item.
+const IMAGE_SIZE: f32 = 200.;
+
+/// Side length of the resize handles shown around selected items.
+const HANDLE_SIZE: f32 = 8.;
+
+/// Distance between the top edge of a selected item and its rotation knob.
+const KNOB_DISTANCE: f32 = 24.;
+
+/// Step the rotation knob snaps to while Shift is held, in degrees.
+const SNAP_ANGLE: f64 = 15.;
+
+/// Smallest factor an item can be scaled down to.
+const MIN_SCALE: f32 = 0.1;
+
+/// Where the resize handles sit on the frame, as steps from its centre
+/// towards its edges, with the cursor shown over each.
+const HANDLES: [(f32, f32, &str); 8] = [
+    (-1., -1., "nwse-resize"),
+    (0., -1., "ns-resize"),
+    (1., -1., "nesw-resize"),
+    (1., 0., "ew-resize"),
+    (1., 1., "nwse-resize"),
+    (0., 1., "ns-resize"),
+    (-1., 1., "nesw-resize"),
+    (-1., 0., "ew-resize"),
+];
 
 glib::wrapper! {
     pub struct CanvasItem(ObjectSubclass<ExCanvasItem>) @extends gtk::Widget;
@@ -52,105 +94,287 @@ impl CanvasItem {
         *imp.r.borrow() as i32
     }
 
-    pub fn is_editing(&self) -> bool {
+    /// Identifies the item within its canvas. Empty until the item is
+    /// added to a canvas, which assigns a fresh id when needed.
+    pub fn id(&self) -> String {
         let imp = self.imp();
-        imp.editor.borrow().is_some()
+        imp.id.borrow().clone()
     }
 
-    pub fn stop_editing(&self) {
-        if !self.is_editing() {
-            return;
-        }
+    pub fn set_id(&self, id: &str) {
         let imp = self.imp();
+        *imp.id.borrow_mut() = id.to_string();
+    }
 
-        let editor = imp.editor.borrow();
-        let editor = editor.as_ref().cloned().unwrap();
+    /// Size of the item's frame before rotation and scaling. Items that have not been
+    /// allocated yet report the size they ask for.
+    pub fn size(&self) -> (i32, i32) {
+        let imp = self.imp();
+        let frame = imp.frame.borrow();
+        let frame = frame.as_ref().unwrap();
+        let (width, height) = (frame.allocated_width(), frame.allocated_height());
+        if width > 0 && height > 0 {
+            return (width, height);
+        }
+        let width = frame.measure(Orientation::Horizontal, -1).1;
+        let height = frame.measure(Orientation::Vertical, width).1;
+        (width, height)
+    }
 
-        let fixed = imp.fixed.borrow();
-        let fixed = fixed.as_ref().cloned().unwrap();
-        let fixed: Fixed = fixed.downcast().unwrap();
-        fixed.remove(&editor);
+    pub fn texture(&self) -> Option<Texture> {
+        let imp = self.imp();
+        let picture = imp.picture.borrow();
+        let picture = picture.as_ref()?.downcast_ref::<Picture>().unwrap();
+        picture.paintable()?.downcast().ok()
+    }
 
-        imp.editor.replace(None);
+    /// Shows `texture` above the label, scaled down to fit `IMAGE_SIZE`.
+    pub fn set_texture(&self, texture: Option<&Texture>) {
+        let imp = self.imp();
+        if let Some(picture) = imp.picture.borrow_mut().take() {
+            picture.unparent();
+        }
+        unsafe {
+            self.steal_data::<String>("image-data");
+        }
+        let texture = match texture {
+            Some(texture) => texture,
+            None => return self.emit_changed(),
+        };
+
+        let (width, height) = image_size(texture);
+        let picture = Picture::for_paintable(texture);
+        picture.set_can_shrink(true);
+        picture.set_size_request(width as i32, height as i32);
+
+        let frame = imp.frame.borrow();
+        let frame = frame.as_ref().cloned().unwrap();
+        let frame: Box = frame.downcast().unwrap();
+        frame.prepend(&picture);
+        *imp.picture.borrow_mut() = Some(picture.upcast::<gtk::Widget>());
+        self.emit_changed();
     }
 
-    pub fn start_editing(&self) {
-        let item = self.imp();
-        let canvas = self.parent().unwrap();
-        if self.is_editing() {
-            return;
+    /// The image as base64 encoded PNG, encoded once and then cached.
+    pub fn image_data(&self) -> Option<String> {
+        unsafe {
+            if let Some(data) = self.data::<String>("image-data") {
+                return Some(data.as_ref().clone());
+            }
         }
+        let data = glib::base64_encode(&self.texture()?.save_to_png_bytes()).to_string();
+        unsafe {
+            self.set_data("image-data", data.clone());
+        }
+        Some(data)
+    }
 
-        let box1 = Box::new(Orientation::Vertical, 12);
-        *item.editor.borrow_mut() = Some(box1.upcast::<gtk::Widget>());
+    pub fn set_image_data(&self, data: Option<&str>) {
+        let texture = data.and_then(|data| {
+            Texture::from_bytes(&glib::Bytes::from_owned(glib::base64_decode(data))).ok()
+        });
+        self.set_texture(texture.as_ref());
+        if let (Some(data), Some(_)) = (data, texture) {
+            unsafe {
+                self.set_data("image-data", data.to_string());
+            }
+        }
+    }
 
-        let entry = Entry::new();
+    pub fn label_widget(&self) -> Label {
+        let imp = self.imp();
+        let label = imp.label.borrow();
+        label.as_ref().cloned().unwrap().downcast().unwrap()
+    }
 
-        let label = item.label.borrow();
+    pub fn label(&self) -> String {
+        let imp = self.imp();
+        let label = imp.label.borrow();
         let label = label.as_ref().cloned().unwrap();
         let label = label.downcast::<Label>().unwrap();
-        entry.set_text(&label.text());
-
-        entry.set_width_chars(12);
-        entry.connect_notify_local(
-            Some("text"),
-            clone!(@weak self as tag => move |editable, _pspec| {
-                let imp = tag.imp();
-                let label = imp.label.borrow();
-                let label = label.as_ref().cloned().unwrap();
-                let label = label.downcast::<Label>().unwrap();
-                label.set_text(&editable.text());
-            }),
-        );
-        entry.connect_activate(clone!(@weak self as this => move|_entry| {
-            if !this.is_editing() {
-                return;
-            }
-            let imp = this.imp();
-            {
-                let editor = imp.editor.borrow();
-                let editor = editor.as_ref().cloned().unwrap();
-                let fixed = editor.parent().unwrap();
-                let fixed:Fixed = fixed.downcast().unwrap();
-                fixed.remove(&editor);
-            }
-            imp.editor.replace(None);
-        }));
+        label.text().to_string()
+    }
 
-        let scale = Scale::with_range(Orientation::Horizontal, 0., 360., 1.);
-        scale.set_draw_value(false);
-        scale.set_value(*item.angle.borrow() as f64 % 360.);
+    pub fn set_label(&self, text: &str) {
+        let imp = self.imp();
+        let label = imp.label.borrow();
+        let label = label.as_ref().cloned().unwrap();
+        let label = label.downcast::<Label>().unwrap();
+        label.set_text(text);
+        self.notify("label");
+        self.emit_changed();
+    }
 
-        scale.connect_value_changed(clone!(@weak self as this => move |range| {
-            let item = this.imp();
-            *item.angle.borrow_mut() = range.value() as f32;
-            item.apply_transform();
-        }));
+    pub fn angle(&self) -> f32 {
+        let imp = self.imp();
+        *imp.angle.borrow()
+    }
+
+    pub fn set_angle(&self, angle: f32) {
+        let imp = self.imp();
+        *imp.angle.borrow_mut() = angle;
+        imp.apply_transform();
+        self.notify("angle");
+        self.emit_changed();
+    }
 
-        let editor = item.editor.borrow();
-        let editor = editor.as_ref().cloned().unwrap();
-        let editor: Box = editor.downcast().unwrap();
-        editor.append(&entry);
-        editor.append(&scale);
+    /// Horizontal and vertical scale factors of the frame.
+    pub fn scale(&self) -> (f32, f32) {
+        let imp = self.imp();
+        (*imp.scale_x.borrow(), *imp.scale_y.borrow())
+    }
 
-        if let Some((x, y)) = self.translate_coordinates(&canvas, 0., 0.) {
-            let canvas: Fixed = canvas.downcast().unwrap();
-            canvas.put(&editor, x, y + 2. * *item.r.borrow() as f64);
+    pub fn set_scale(&self, scale_x: f32, scale_y: f32) {
+        let imp = self.imp();
+        let (scale_x, scale_y) = (scale_x.max(MIN_SCALE), scale_y.max(MIN_SCALE));
+        if self.scale() == (scale_x, scale_y) {
+            return;
         }
-        entry.grab_focus();
+        *imp.scale_x.borrow_mut() = scale_x;
+        *imp.scale_y.borrow_mut() = scale_y;
+        imp.apply_transform();
+        self.notify("scale-x");
+        self.notify("scale-y");
+        self.emit_changed();
+    }
+
+    pub fn color(&self) -> Option<RGBA> {
+        let imp = self.imp();
+        let frame = imp.frame.borrow();
+        let frame = frame.as_ref().unwrap();
+        unsafe { frame.data::<RGBA>("color").map(|color| *color.as_ref()) }
+    }
+
+    pub fn set_color(&self, color: RGBA) {
+        let imp = self.imp();
+        imp.set_label_color(color);
+    }
+
+    pub fn css_class(&self) -> Option<String> {
+        let imp = self.imp();
+        let frame = imp.frame.borrow();
+        let frame = frame.as_ref().unwrap();
+        unsafe {
+            frame
+                .data::<String>("css-class")
+                .map(|class| class.as_ref().clone())
+        }
+    }
+
+    pub fn set_css_class(&self, class: &str) {
+        let imp = self.imp();
+        imp.set_css(class.to_string());
+    }
+
+    /// Position of the item's top-left corner on its canvas.
+    pub fn position(&self) -> (f64, f64) {
+        self.parent()
+            .and_then(|parent| parent.downcast::<Canvas>().ok())
+            .map_or((0., 0.), |canvas| canvas.child_position(self))
+    }
+
+    /// Moves the item. Does nothing while the item is not on a canvas.
+    pub fn set_position(&self, x: f64, y: f64) {
+        if let Some(canvas) = self
+            .parent()
+            .and_then(|parent| parent.downcast::<Canvas>().ok())
+        {
+            canvas.move_item(self, x, y);
+        }
+    }
+
+    /// Locked items cannot be dragged or rotated by the user, but can still
+    /// be changed programmatically.
+    pub fn is_locked(&self) -> bool {
+        self.imp().locked.get()
+    }
+
+    pub fn set_locked(&self, locked: bool) {
+        let imp = self.imp();
+        if imp.locked.replace(locked) != locked {
+            imp.update_handles();
+            self.notify("locked");
+            self.emit_changed();
+        }
+    }
+
+    /// Lets the canvas know that the label, angle or style changed.
+    fn emit_changed(&self) {
+        if let Some(canvas) = self
+            .parent()
+            .and_then(|parent| parent.downcast::<Canvas>().ok())
+        {
+            canvas.item_changed(self);
+        }
+    }
+
+    /// Bounding box of the rotated frame in the coordinate space of `target`.
+    pub fn bounds(&self, target: &impl IsA<gtk::Widget>) -> Option<Rect> {
+        let imp = self.imp();
+        let frame = imp.frame.borrow();
+        frame.as_ref().unwrap().compute_bounds(target)
+    }
+
+    pub fn is_selected(&self) -> bool {
+        let imp = self.imp();
+        let frame = imp.frame.borrow();
+        frame.as_ref().unwrap().has_css_class("selected")
+    }
+
+    pub fn set_selected(&self, selected: bool) {
+        let imp = self.imp();
+        let frame = imp.frame.borrow();
+        let frame = frame.as_ref().unwrap();
+        if selected {
+            frame.add_css_class("selected");
+        } else {
+            frame.remove_css_class("selected");
+        }
+        // Redraw right away so a snapshot taken before the next frame, as
+        // the exports do, already has the outline changed.
+        frame.queue_draw();
+        imp.update_handles();
     }
 }
 
 #[derive(Debug, Default)]
 pub struct ExCanvasItem {
+    id: RefCell<String>,
     fixed: RefCell<Option<gtk::Widget>>,
+    frame: RefCell<Option<gtk::Widget>>,
     label: RefCell<Option<gtk::Widget>>,
+    picture: RefCell<Option<gtk::Widget>>,
+    handles: RefCell<Vec<gtk::Widget>>,
+    knob: RefCell<Option<gtk::Widget>>,
+    angle_label: RefCell<Option<gtk::Widget>>,
 
     angle: RefCell<f32>,
     delta: RefCell<f32>,
     r: RefCell<f32>,
+    scale_x: RefCell<f32>,
+    scale_y: RefCell<f32>,
+    locked: Cell<bool>,
+
+    resize: RefCell<Option<Resize>>,
+    rotation: RefCell<Option<Rotation>>,
+}
 
-    editor: RefCell<Option<gtk::Widget>>,
+/// State of a rotation started from the knob.
+#[derive(Debug)]
+struct Rotation {
+    /// Centre of the item in canvas coordinates.
+    centre: (f64, f64),
+    /// Direction from the centre to where the knob was grabbed, in degrees.
+    start: f64,
+}
+
+/// State of a resize started from one of the handles.
+#[derive(Debug)]
+struct Resize {
+    handle: (f32, f32),
+    /// The opposite corner or edge, which stays put, in canvas coordinates.
+    anchor: (f64, f64),
+    before: Option<ItemData>,
 }
 
 #[glib::object_subclass]
@@ -168,18 +392,17 @@ impl ObjectSubclass for ExCanvasItem {
 impl ObjectImpl for ExCanvasItem {
     fn constructed(&self, obj: &Self::Type) {
         self.parent_constructed(obj);
-        unsafe {
-            ITEM_ID += 1;
-        }
-        *self.editor.borrow_mut() = None;
-
-        let label = unsafe { format!("Item {}", ITEM_ID) };
-        let label = gtk::Label::new(Some(&label));
+        let label = gtk::Label::new(Some("Item"));
         *self.label.borrow_mut() = Some(label.upcast::<gtk::Widget>());
         let label = self.label.borrow();
         let label = label.as_ref().cloned().unwrap();
         let label = label.downcast::<Label>().unwrap();
-        label.add_css_class("canvasitem");
+
+        let frame = Box::new(Orientation::Vertical, 6);
+        frame.add_css_class("canvasitem");
+        frame.add_css_class("frame");
+        frame.append(&label);
+        *self.frame.borrow_mut() = Some(frame.clone().upcast::<gtk::Widget>());
 
         let fixed = Fixed::new();
         *self.fixed.borrow_mut() = Some(fixed.upcast::<gtk::Widget>());
@@ -187,10 +410,7 @@ impl ObjectImpl for ExCanvasItem {
         let fixed = fixed.as_ref().cloned().unwrap();
         let fixed = fixed.downcast::<Fixed>().unwrap();
         fixed.set_parent(obj);
-        fixed.put(&label, 0., 0.);
-        label.add_css_class("frame");
-        let id = unsafe { format!("item{}", ITEM_ID) };
-        label.set_widget_name(&id);
+        fixed.put(&frame, 0., 0.);
 
         let mut color = RGBA::parse("yellow").unwrap();
         if theme_is_dark() {
@@ -200,58 +420,247 @@ impl ObjectImpl for ExCanvasItem {
         self.set_label_color(color);
 
         *self.angle.borrow_mut() = 0.;
+        *self.scale_x.borrow_mut() = 1.;
+        *self.scale_y.borrow_mut() = 1.;
+
+        for (x, y, cursor) in HANDLES {
+            let handle = Box::new(Orientation::Horizontal, 0);
+            handle.add_css_class("handle");
+            handle.set_size_request(HANDLE_SIZE as i32, HANDLE_SIZE as i32);
+            handle.set_cursor_from_name(Some(cursor));
+            handle.set_visible(false);
+            fixed.put(&handle, 0., 0.);
+
+            let drag = GestureDrag::new();
+            drag.connect_drag_begin(clone!(@weak obj => move |gesture, _, _| {
+                if obj.is_locked() {
+                    gesture.set_state(EventSequenceState::Denied);
+                    return;
+                }
+                gesture.set_state(EventSequenceState::Claimed);
+                obj.imp().begin_resize((x, y));
+            }));
+            drag.connect_drag_update(clone!(@weak obj => move |gesture, _, _| {
+                let handle = gesture.widget();
+                let point = gesture.point(None).and_then(|(x, y)| {
+                    handle.translate_coordinates(&obj.parent()?, x, y)
+                });
+                if let Some((x, y)) = point {
+                    let uniform = gesture.current_event_state().contains(ModifierType::SHIFT_MASK);
+                    obj.imp().update_resize(x, y, uniform);
+                }
+            }));
+            drag.connect_drag_end(clone!(@weak obj => move |_, _, _| {
+                if let Some(resize) = obj.imp().resize.take() {
+                    history::record_change(&obj, resize.before, None);
+                }
+            }));
+            handle.add_controller(&drag);
+            self.handles.borrow_mut().push(handle.upcast());
+        }
+
+        let knob = Box::new(Orientation::Horizontal, 0);
+        knob.add_css_class("handle");
+        knob.add_css_class("knob");
+        knob.set_size_request(HANDLE_SIZE as i32, HANDLE_SIZE as i32);
+        knob.set_cursor_from_name(Some("grab"));
+        knob.set_visible(false);
+        fixed.put(&knob, 0., 0.);
+
+        let angle_label = Label::new(None);
+        angle_label.add_css_class("osd");
+        angle_label.add_css_class("angle");
+        angle_label.set_visible(false);
+        fixed.put(&angle_label, 0., 0.);
+
+        let drag = GestureDrag::new();
+        drag.connect_drag_begin(clone!(@weak obj => move |gesture, x, y| {
+            let knob = gesture.widget();
+            let point = obj
+                .parent()
+                .and_then(|canvas| knob.translate_coordinates(&canvas, x, y));
+            match point {
+                Some((x, y)) if !obj.is_locked() => {
+                    gesture.set_state(EventSequenceState::Claimed);
+                    obj.imp().begin_rotation(x, y);
+                }
+                _ => {
+                    gesture.set_state(EventSequenceState::Denied);
+                }
+            }
+        }));
+        drag.connect_drag_update(clone!(@weak obj => move |gesture, _, _| {
+            let knob = gesture.widget();
+            let point = gesture.point(None).and_then(|(x, y)| {
+                knob.translate_coordinates(&obj.parent()?, x, y)
+            });
+            if let Some((x, y)) = point {
+                let snap = gesture.current_event_state().contains(ModifierType::SHIFT_MASK);
+                obj.imp().update_rotation(x, y, snap);
+            }
+        }));
+        drag.connect_drag_end(clone!(@weak obj => move |_, _, _| {
+            let imp = obj.imp();
+            if imp.rotation.take().is_some() {
+                imp.angle_label.borrow().as_ref().unwrap().set_visible(false);
+                imp.commit_rotation();
+            }
+        }));
+        knob.add_controller(&drag);
+        *self.knob.borrow_mut() = Some(knob.upcast());
+        *self.angle_label.borrow_mut() = Some(angle_label.upcast());
 
         let dest = DropTarget::new(Type::INVALID, DragAction::COPY);
-        dest.set_types(&[RGBA::static_type(), Type::STRING]);
+        dest.set_types(&[RGBA::static_type(), Style::static_type(), Type::STRING]);
+        // Dragged items also offer their labels as text; leave those drops
+        // to the canvas so they move or copy the items instead.
+        dest.connect_accept(|dest, drop| {
+            let formats = drop.formats();
+            !formats.contain_mime_type(ITEMS_MIME_TYPE)
+                && dest
+                    .formats()
+                    .is_some_and(|accepted| formats.match_(&accepted))
+                && drop.actions().intersects(dest.actions())
+        });
         dest.connect_drop(
             clone!(@weak obj => @default-return false, move |_dest, value, _x, _y| {
                 let item = obj.imp();
+                let before = ItemData::capture(&obj);
                 if value.type_() == RGBA::static_type() {
                     item.set_label_color(value.get::<RGBA>().unwrap());
+                } else if value.type_() == Style::static_type() {
+                    let style = value.get::<Style>().unwrap();
+                    if !is_valid_class_name(&style.class) {
+                        item.flash_rejected();
+                        return false;
+                    }
+                    item.set_css(style.class);
                 } else if value.type_() == Type::STRING {
-                    item.set_css(value.get::<String>().unwrap());
+                    let text = value.get::<String>().unwrap();
+                    match parse_hex_color(&text) {
+                        Some(color) => item.set_label_color(color),
+                        None => obj.set_label(text.trim()),
+                    }
                 }
+                history::record_change(&obj, before, None);
                 true
             }),
         );
-        label.add_controller(&dest);
+        frame.add_controller(&dest);
 
         let gesture = GestureRotate::new();
         gesture.connect_angle_changed(clone!(@weak obj => move | _gesture, angle, _delta | {
                 let item = obj.imp();
-                *item.delta.borrow_mut() = (angle as f64 / PI * 180.) as f32;
+                if item.locked.get() {
+                    return;
+                }
+                *item.delta.borrow_mut() = (angle / PI * 180.) as f32;
                 item.apply_transform();
             }
         ));
 
         gesture.connect_end(clone!(@weak obj=>move|_, _| {
-            let item = obj.imp();
-            *item.angle.borrow_mut() = *item.angle.borrow() + *item.delta.borrow();
-            *item.delta.borrow_mut() = 0.;
+            obj.imp().commit_rotation();
         }));
         obj.add_controller(&gesture);
 
         let gesture = GestureClick::new();
         gesture.connect_released(clone!(@weak obj =>move | _, _, _, _ | {
                 let canvas = obj.parent().unwrap();
+                let raise = canvas
+                    .downcast_ref::<Canvas>()
+                    .is_none_or(Canvas::has_auto_raise);
                 let last_child = canvas.last_child().unwrap();
 
-                if obj != last_child {
+                if raise && obj != last_child {
+                    let before = ItemData::capture(&obj);
                     obj.insert_after(&canvas, Some(&last_child));
+                    history::record_change(&obj, before, None);
                 }
             }
         ));
         obj.add_controller(&gesture);
+
+        obj.connect_notify_local(Some("opacity"), |obj, _| obj.emit_changed());
+    }
+
+    fn properties() -> &'static [ParamSpec] {
+        static PROPERTIES: Lazy<Vec<ParamSpec>> = Lazy::new(|| {
+            let flags = ParamFlags::READWRITE | ParamFlags::EXPLICIT_NOTIFY;
+            vec![
+                ParamSpecString::new("label", "label", "label", None, flags),
+                ParamSpecFloat::new("angle", "angle", "angle", f32::MIN, f32::MAX, 0., flags),
+                ParamSpecFloat::new(
+                    "scale-x",
+                    "scale-x",
+                    "scale-x",
+                    MIN_SCALE,
+                    f32::MAX,
+                    1.,
+                    flags,
+                ),
+                ParamSpecFloat::new(
+                    "scale-y",
+                    "scale-y",
+                    "scale-y",
+                    MIN_SCALE,
+                    f32::MAX,
+                    1.,
+                    flags,
+                ),
+                ParamSpecBoxed::new("color", "color", "color", RGBA::static_type(), flags),
+                ParamSpecString::new("css-class", "css-class", "css-class", None, flags),
+                ParamSpecDouble::new("x", "x", "x", f64::MIN, f64::MAX, 0., flags),
+                ParamSpecDouble::new("y", "y", "y", f64::MIN, f64::MAX, 0., flags),
+                ParamSpecBoolean::new("locked", "locked", "locked", false, flags),
+            ]
+        });
+        PROPERTIES.as_ref()
+    }
+
+    fn property(&self, obj: &Self::Type, _id: usize, pspec: &ParamSpec) -> glib::Value {
+        match pspec.name() {
+            "label" => obj.label().to_value(),
+            "angle" => obj.angle().to_value(),
+            "scale-x" => obj.scale().0.to_value(),
+            "scale-y" => obj.scale().1.to_value(),
+            "color" => obj.color().to_value(),
+            "css-class" => obj.css_class().to_value(),
+            "x" => obj.position().0.to_value(),
+            "y" => obj.position().1.to_value(),
+            "locked" => obj.is_locked().to_value(),
+            _ => unimplemented!(),
+        }
+    }
+
+    fn set_property(&self, obj: &Self::Type, _id: usize, value: &glib::Value, pspec: &ParamSpec) {
+        match pspec.name() {
+            "label" => obj.set_label(&value.get::<Option<String>>().unwrap().unwrap_or_default()),
+            "angle" => obj.set_angle(value.get().unwrap()),
+            "scale-x" => obj.set_scale(value.get().unwrap(), obj.scale().1),
+            "scale-y" => obj.set_scale(obj.scale().0, value.get().unwrap()),
+            "color" => {
+                if let Some(color) = value.get::<Option<RGBA>>().unwrap() {
+                    obj.set_color(color);
+                }
+            }
+            "css-class" => {
+                if let Some(class) = value.get::<Option<String>>().unwrap() {
+                    obj.set_css_class(&class);
+                }
+            }
+            "x" => obj.set_position(value.get().unwrap(), obj.position().1),
+            "y" => obj.set_position(obj.position().0, value.get().unwrap()),
+            "locked" => obj.set_locked(value.get().unwrap()),
+            _ => unimplemented!(),
+        }
     }
 
     fn dispose(&self, _obj: &Self::Type) {
         if let Some(child) = self.fixed.borrow_mut().take() {
             child.unparent();
         }
-        if let Some(child) = self.label.borrow_mut().take() {
-            child.unparent();
-        }
-        if let Some(child) = self.editor.borrow_mut().take() {
+        if let Some(child) = self.frame.borrow_mut().take() {
             child.unparent();
         }
     }
@@ -266,74 +675,247 @@ impl WidgetImpl for ExCanvasItem {
 
 impl ExCanvasItem {
     fn apply_transform(&self) {
-        let label = self.label.borrow();
-        let label = label.as_ref().cloned().unwrap();
-        let x = label.allocated_width() as f32 / 2.;
-        let y = label.allocated_height() as f32 / 2.;
-        let r = (x * x + y * y).sqrt();
+        let frame = self.frame.borrow();
+        let frame = frame.as_ref().cloned().unwrap();
+        let (transform, x, y) = self.transform();
+        let fixed = self.fixed.borrow();
+        let fixed = fixed.as_ref().cloned().unwrap();
+        let fixed = fixed.downcast::<Fixed>().unwrap();
+        fixed.set_child_transform(&frame, Some(&transform));
+
+        for (handle, (hx, hy, _)) in self.handles.borrow().iter().zip(HANDLES) {
+            let point = transform.transform_point(&Point::new(x + hx * x, y + hy * y));
+            let offset = Point::new(point.x() - HANDLE_SIZE / 2., point.y() - HANDLE_SIZE / 2.);
+            fixed.set_child_transform(handle, Transform::new().translate(&offset).as_ref());
+        }
+
+        // The knob sits on the line from the centre through the middle of
+        // the top edge, so it turns with the item.
+        let r = *self.r.borrow();
+        let top = transform.transform_point(&Point::new(x, 0.));
+        let (dx, dy) = (top.x() - r, top.y() - r);
+        let length = (dx * dx + dy * dy).sqrt();
+        let (dx, dy) = if length > 0. {
+            (dx / length, dy / length)
+        } else {
+            (0., -1.)
+        };
+        let knob_x = top.x() + dx * KNOB_DISTANCE;
+        let knob_y = top.y() + dy * KNOB_DISTANCE;
+        if let Some(knob) = self.knob.borrow().as_ref() {
+            let offset = Point::new(knob_x - HANDLE_SIZE / 2., knob_y - HANDLE_SIZE / 2.);
+            fixed.set_child_transform(knob, Transform::new().translate(&offset).as_ref());
+        }
+        if let Some(label) = self.angle_label.borrow().as_ref() {
+            let offset = Point::new(knob_x + HANDLE_SIZE, knob_y - HANDLE_SIZE);
+            fixed.set_child_transform(label, Transform::new().translate(&offset).as_ref());
+        }
+    }
+
+    /// Maps the frame into the item, centred in a square of side `2 * r`
+    /// so it can rotate freely. Also returns half the frame's unscaled size.
+    fn transform(&self) -> (Transform, f32, f32) {
+        let frame = self.frame.borrow();
+        let frame = frame.as_ref().unwrap();
+        let x = frame.allocated_width() as f32 / 2.;
+        let y = frame.allocated_height() as f32 / 2.;
+        let (scale_x, scale_y) = (*self.scale_x.borrow(), *self.scale_y.borrow());
+        let r = ((x * scale_x).powi(2) + (y * scale_y).powi(2)).sqrt();
         *self.r.borrow_mut() = r;
-        let transform = gtk::gsk::Transform::new();
-        let transform = transform
-            .translate(&gtk::graphene::Point::new(r, r))
-            .unwrap();
+        let transform = Transform::new();
+        let transform = transform.translate(&Point::new(r, r)).unwrap();
         let transform = transform
             .rotate(*self.angle.borrow() + *self.delta.borrow())
             .unwrap();
-        let transform = transform
-            .translate(&gtk::graphene::Point::new(-x, -y))
-            .unwrap();
-        let fixed = self.fixed.borrow();
-        let fixed = fixed.as_ref().cloned().unwrap();
-        let fixed = fixed.downcast::<Fixed>().unwrap();
-        fixed.set_child_transform(&label, Some(&transform));
+        let transform = transform.scale(scale_x, scale_y).unwrap();
+        let transform = transform.translate(&Point::new(-x, -y)).unwrap();
+        (transform, x, y)
+    }
+
+    /// Shows the resize handles while the item is selected and not locked.
+    fn update_handles(&self) {
+        let obj = self.instance();
+        let visible = obj.is_selected() && !obj.is_locked();
+        for handle in self.handles.borrow().iter() {
+            handle.set_visible(visible);
+        }
+        if let Some(knob) = self.knob.borrow().as_ref() {
+            knob.set_visible(visible);
+        }
+    }
+
+    fn begin_rotation(&self, x: f64, y: f64) {
+        let obj = self.instance();
+        let (left, top) = obj.position();
+        let r = *self.r.borrow() as f64;
+        let centre = (left + r, top + r);
+        *self.rotation.borrow_mut() = Some(Rotation {
+            centre,
+            start: (y - centre.1).atan2(x - centre.0).to_degrees(),
+        });
+        self.show_angle(obj.angle() as f64);
+    }
+
+    /// Turns the item by how far the pointer at `(x, y)` on the canvas has
+    /// moved around its centre. `snap` rounds the result to `SNAP_ANGLE`.
+    fn update_rotation(&self, x: f64, y: f64, snap: bool) {
+        let (centre, start) = match self.rotation.borrow().as_ref() {
+            Some(rotation) => (rotation.centre, rotation.start),
+            None => return,
+        };
+        let angle = *self.angle.borrow() as f64;
+        let mut delta = (y - centre.1).atan2(x - centre.0).to_degrees() - start;
+        if snap {
+            delta = ((angle + delta) / SNAP_ANGLE).round() * SNAP_ANGLE - angle;
+        }
+        *self.delta.borrow_mut() = delta as f32;
+        self.apply_transform();
+        self.show_angle(angle + delta);
+    }
+
+    fn show_angle(&self, angle: f64) {
+        let label = self.angle_label.borrow();
+        let label = label.as_ref().unwrap().downcast_ref::<Label>().unwrap();
+        label.set_text(&format!("{:.0}°", angle.rem_euclid(360.)));
+        label.set_visible(true);
+    }
+
+    /// Folds the rotation shown while a gesture or the knob is active into
+    /// `angle` and records it.
+    fn commit_rotation(&self) {
+        let obj = self.instance();
+        let before = ItemData::capture(&obj);
+        *self.angle.borrow_mut() = *self.angle.borrow() + *self.delta.borrow();
+        *self.delta.borrow_mut() = 0.;
+        obj.notify("angle");
+        obj.emit_changed();
+        history::record_change(&obj, before, None);
+    }
+
+    fn begin_resize(&self, handle: (f32, f32)) {
+        let obj = self.instance();
+        let (transform, x, y) = self.transform();
+        let anchor = transform.transform_point(&Point::new(x - handle.0 * x, y - handle.1 * y));
+        let (left, top) = obj.position();
+        *self.resize.borrow_mut() = Some(Resize {
+            handle,
+            anchor: (left + anchor.x() as f64, top + anchor.y() as f64),
+            before: ItemData::capture(&obj),
+        });
+    }
+
+    /// Scales the frame so the dragged handle follows the pointer at
+    /// `(x, y)` on the canvas. `uniform` keeps the aspect ratio.
+    fn update_resize(&self, x: f64, y: f64, uniform: bool) {
+        let obj = self.instance();
+        let (handle, (anchor_x, anchor_y)) = match self.resize.borrow().as_ref() {
+            Some(resize) => (resize.handle, resize.anchor),
+            None => return,
+        };
+        let (width, height) = obj.size();
+        let angle = (*self.angle.borrow() as f64).to_radians();
+        let (dx, dy) = (x - anchor_x, y - anchor_y);
+        let u = (dx * angle.cos() + dy * angle.sin()) as f32;
+        let v = (dy * angle.cos() - dx * angle.sin()) as f32;
+
+        let (mut scale_x, mut scale_y) = obj.scale();
+        if handle.0 != 0. {
+            scale_x = (u * handle.0 / width as f32).max(MIN_SCALE);
+        }
+        if handle.1 != 0. {
+            scale_y = (v * handle.1 / height as f32).max(MIN_SCALE);
+        }
+        if uniform {
+            if handle.0 == 0. {
+                scale_x = scale_y;
+            } else if handle.1 == 0. {
+                scale_y = scale_x;
+            } else {
+                scale_x = scale_x.max(scale_y);
+                scale_y = scale_x;
+            }
+        }
+        obj.set_scale(scale_x, scale_y);
+
+        let (transform, x, y) = self.transform();
+        let anchor = transform.transform_point(&Point::new(x - handle.0 * x, y - handle.1 * y));
+        obj.set_position(anchor_x - anchor.x() as f64, anchor_y - anchor.y() as f64);
     }
 
     fn set_css(&self, class: String) {
-        let label = self.label.borrow();
-        let label = label.as_ref().cloned().unwrap();
-        let context = label.style_context();
+        let frame = self.frame.borrow();
+        let frame = frame.as_ref().cloned().unwrap();
+        let context = frame.style_context();
         unsafe {
-            if let Some(provider) = context.data::<CssProvider>("style-provider") {
-                context.remove_provider(provider.as_ref());
+            if let Some(provider) = context.steal_data::<CssProvider>("style-provider") {
+                context.remove_provider(&provider);
             }
+            frame.steal_data::<RGBA>("color");
         }
         unsafe {
-            if let Some(old_class) = label.data::<String>("css-class") {
-                label.remove_css_class(old_class.as_ref());
+            if let Some(old_class) = frame.data::<String>("css-class") {
+                frame.remove_css_class(old_class.as_ref());
             }
         }
         unsafe {
-            label.set_data::<String>("css-class", class.to_owned());
+            frame.set_data::<String>("css-class", class.to_owned());
         }
-        label.add_css_class(&class);
+        frame.add_css_class(&class);
+        let obj = self.instance();
+        obj.notify("color");
+        obj.notify("css-class");
+        obj.emit_changed();
+    }
+
+    /// Briefly marks the item to show that a drop was refused.
+    fn flash_rejected(&self) {
+        let frame = self.frame.borrow();
+        let frame = frame.as_ref().cloned().unwrap();
+        frame.add_css_class("drop-rejected");
+        glib::timeout_add_local_once(
+            Duration::from_millis(600),
+            clone!(@weak frame => move || frame.remove_css_class("drop-rejected")),
+        );
     }
 
     fn set_label_color(&self, color: RGBA) {
-        let label = self.label.borrow();
-        let label = label.as_ref().cloned().unwrap();
+        let frame = self.frame.borrow();
+        let frame = frame.as_ref().cloned().unwrap();
 
         let color_str = color.to_string();
         let css_str = format!("* {{ background: {}; }}", color_str);
 
-        let context = label.style_context();
+        let context = frame.style_context();
         unsafe {
-            if let Some(provider) = context.data::<CssProvider>("style-provider") {
-                context.remove_provider(provider.as_ref());
+            if let Some(provider) = context.steal_data::<CssProvider>("style-provider") {
+                context.remove_provider(&provider);
             }
-            if let Some(old_class) = label.data::<String>("css-class") {
-                label.remove_css_class(old_class.as_ref());
+            if let Some(old_class) = frame.steal_data::<String>("css-class") {
+                frame.remove_css_class(&old_class);
             }
         }
 
         let provider = CssProvider::new();
         provider.load_from_data(css_str.as_bytes());
-        label.style_context().add_provider(&provider, 800);
+        frame.style_context().add_provider(&provider, 800);
         unsafe {
             context.set_data("style-provider", provider);
+            frame.set_data("color", color);
         }
+        let obj = self.instance();
+        obj.notify("color");
+        obj.notify("css-class");
+        obj.emit_changed();
     }
 }
 
+/// Size `texture` is shown at inside an item.
+pub fn image_size(texture: &Texture) -> (f32, f32) {
+    let (width, height) = (texture.width() as f32, texture.height() as f32);
+    let scale = (IMAGE_SIZE / width.max(height)).min(1.);
+    ((width * scale).round(), (height * scale).round())
+}
+
 fn theme_is_dark() -> bool {
     let settings = Settings::default().unwrap();
     settings.is_gtk_application_prefer_dark_theme()
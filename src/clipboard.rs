@@ -0,0 +1,116 @@
+//
+// clipboard.rs
+// Copyright (C) 2022 gmg137 <gmg137 AT live.com>
+// Distributed under terms of the GPL-3.0-or-later license.
+//
+use gtk::glib::clone;
+use gtk::prelude::*;
+use gtk::{gio, glib};
+
+use crate::canvas::Canvas;
+use crate::document::Document;
+use crate::formats::clipboard_provider;
+use crate::history::remove_items;
+use crate::import::{import_document, import_text};
+
+/// How far each paste is moved from the copied items when the pointer is
+/// not over the canvas.
+const PASTE_OFFSET: f64 = 20.;
+
+/// Puts the selected items on the clipboard. Returns false when nothing is
+/// selected.
+pub fn copy_selection(canvas: &Canvas) -> bool {
+    let items = canvas.selected_items();
+    if items.is_empty() {
+        return false;
+    }
+    if canvas
+        .clipboard()
+        .set_content(Some(&clipboard_provider(&items)))
+        .is_err()
+    {
+        return false;
+    }
+    unsafe {
+        canvas.set_data("paste-count", 0u32);
+    }
+    true
+}
+
+pub fn cut_selection(canvas: &Canvas) {
+    if copy_selection(canvas) {
+        remove_items(canvas, &canvas.selected_items());
+    }
+}
+
+/// Pastes items copied from a canvas, or one item per line of plain text
+/// copied from another application. The pasted items become the selection.
+pub fn paste(canvas: &Canvas) {
+    let clipboard = canvas.clipboard();
+    let formats = clipboard.formats().union_deserialize_types();
+
+    if formats.contains_type(Document::static_type()) {
+        clipboard.read_value_async(
+            Document::static_type(),
+            glib::PRIORITY_DEFAULT,
+            None::<&gio::Cancellable>,
+            clone!(@weak canvas => move |result| {
+                if let Ok(document) = result.map(|value| value.get::<Document>().unwrap()) {
+                    let (x, y) = paste_position(&canvas, &document);
+                    let items = import_document(&canvas, &document, x, y);
+                    canvas.select_items(&items);
+                }
+            }),
+        );
+    } else if formats.contains_type(glib::Type::STRING) {
+        clipboard.read_text_async(
+            None::<&gio::Cancellable>,
+            clone!(@weak canvas => move |result| {
+                if let Ok(Some(text)) = result {
+                    let (x, y) = pointer_position(&canvas).unwrap_or((PASTE_OFFSET, PASTE_OFFSET));
+                    let items = import_text(&canvas, &text, x, y);
+                    canvas.select_items(&items);
+                }
+            }),
+        );
+    }
+}
+
+/// Keeps track of the pointer so pasted items can be placed under it.
+pub fn track_pointer(canvas: &Canvas) {
+    let motion = gtk::EventControllerMotion::new();
+    motion.connect_enter(clone!(@weak canvas => move |_, x, y| unsafe {
+        canvas.set_data("pointer", (x, y));
+    }));
+    motion.connect_motion(clone!(@weak canvas => move |_, x, y| unsafe {
+        canvas.set_data("pointer", (x, y));
+    }));
+    motion.connect_leave(clone!(@weak canvas => move |_| unsafe {
+        canvas.steal_data::<(f64, f64)>("pointer");
+    }));
+    canvas.add_controller(&motion);
+}
+
+fn pointer_position(canvas: &Canvas) -> Option<(f64, f64)> {
+    unsafe {
+        canvas
+            .data::<(f64, f64)>("pointer")
+            .map(|position| *position.as_ref())
+    }
+}
+
+/// Top-left corner for pasting `document`: the pointer when it is over the
+/// canvas, otherwise a little further from the copied items on every paste.
+fn paste_position(canvas: &Canvas, document: &Document) -> (f64, f64) {
+    if let Some(position) = pointer_position(canvas) {
+        return position;
+    }
+    let count = unsafe {
+        let count = canvas.data::<u32>("paste-count").map_or(0, |n| *n.as_ref()) + 1;
+        canvas.set_data("paste-count", count);
+        count
+    };
+    let offset = count as f64 * PASTE_OFFSET;
+    let (left, top) = document.origin();
+    (left + offset, top + offset)
+}
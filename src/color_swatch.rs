@@ -5,7 +5,6 @@
 //
 use glib::clone;
 use glib::{ParamSpec, ParamSpecString};
-use gtk::gdk::ContentProvider;
 use gtk::gdk::RGBA;
 use gtk::glib;
 use gtk::graphene::Rect;
@@ -17,6 +16,8 @@ use gtk::Snapshot;
 use once_cell::sync::Lazy;
 use std::cell::RefCell;
 
+use crate::formats::color_provider;
+
 glib::wrapper! {
     pub struct ColorSwatch(ObjectSubclass<ExColorSwatch>) @extends gtk::Widget;
 }
@@ -54,7 +55,7 @@ impl ObjectImpl for ExColorSwatch {
                 let color = swatch.color.borrow();
                 let color = color.as_ref().cloned().unwrap();
                 let rgba = RGBA::parse(&color).unwrap();
-                Some(ContentProvider::for_value(&rgba.to_value()))
+                Some(color_provider(&rgba))
             }),
         );
 
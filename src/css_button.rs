@@ -5,7 +5,6 @@
 //
 use glib::clone;
 use glib::{ParamSpec, ParamSpecString};
-use gtk::gdk::ContentProvider;
 use gtk::subclass::prelude::*;
 use gtk::DragSource;
 use gtk::{glib, Widget};
@@ -13,6 +12,8 @@ use gtk::{prelude::*, Image};
 use once_cell::sync::Lazy;
 use std::cell::RefCell;
 
+use crate::formats::Style;
+
 glib::wrapper! {
     pub struct CssButton(ObjectSubclass<ExCssButton>)
         @extends gtk::Widget;
@@ -61,7 +62,7 @@ impl ObjectImpl for ExCssButton {
                 source.set_icon(paintable.as_ref(), 0, 0);
                 let color = imp.color.borrow();
                 let color = color.as_ref().unwrap();
-                Some(ContentProvider::for_value(&color.to_value()))
+                Some(Style::new(color).provider())
             }),
         );
         obj.add_controller(&source);
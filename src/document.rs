@@ -0,0 +1,336 @@
+//
+// document.rs
+// Copyright (C) 2022 gmg137 <gmg137 AT live.com>
+// Distributed under terms of the GPL-3.0-or-later license.
+//
+use gtk::gdk::RGBA;
+use gtk::glib;
+use gtk::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+use crate::canvas::Canvas;
+use crate::canvas_item::CanvasItem;
+use crate::formats::is_valid_class_name;
+
+/// Version written into every saved document. Bump it whenever the layout
+/// of [`Document`] changes and teach [`migrate`] how to upgrade older files.
+pub const FORMAT_VERSION: u64 = 5;
+
+#[derive(Debug, Clone, Serialize, Deserialize, glib::Boxed)]
+#[boxed_type(name = "Gtk4DndDocument")]
+pub struct Document {
+    pub version: u64,
+    pub items: Vec<ItemData>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ItemData {
+    pub id: String,
+    pub x: f64,
+    pub y: f64,
+    pub angle: f32,
+    #[serde(default = "default_scale")]
+    pub scale_x: f32,
+    #[serde(default = "default_scale")]
+    pub scale_y: f32,
+    pub label: String,
+    #[serde(default)]
+    pub color: Option<String>,
+    #[serde(default)]
+    pub css_class: Option<String>,
+    /// Base64 encoded PNG shown above the label.
+    #[serde(default)]
+    pub image: Option<String>,
+    #[serde(default)]
+    pub locked: bool,
+    #[serde(default = "default_opacity")]
+    pub opacity: f64,
+    pub z: u32,
+}
+
+impl Document {
+    pub fn from_canvas(canvas: &Canvas) -> Self {
+        Self::from_items(&canvas.items())
+    }
+
+    /// Snapshots `items`, which must be placed on a canvas.
+    pub fn from_items(items: &[CanvasItem]) -> Self {
+        Self {
+            version: FORMAT_VERSION,
+            items: items.iter().filter_map(ItemData::capture).collect(),
+        }
+    }
+
+    /// Replaces every `CanvasItem` on `canvas` with the items of this document.
+    pub fn populate(&self, canvas: &Canvas) {
+        for item in canvas.items() {
+            canvas.remove_item(&item);
+        }
+
+        let mut items = self.items.clone();
+        items.sort_by_key(|data| data.z);
+        for data in items {
+            canvas.add_item(&data.to_item(), data.x, data.y);
+        }
+    }
+
+    /// Top-left corner of the items' positions.
+    pub fn origin(&self) -> (f64, f64) {
+        let left = self
+            .items
+            .iter()
+            .map(|data| data.x)
+            .fold(f64::INFINITY, f64::min);
+        let top = self
+            .items
+            .iter()
+            .map(|data| data.y)
+            .fold(f64::INFINITY, f64::min);
+        (left, top)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::from_json(&fs::read(path)?)
+    }
+
+    pub fn from_json(json: &[u8]) -> Result<Self> {
+        let value = migrate(serde_json::from_slice(json)?)?;
+        Ok(serde_json::from_value(value)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let text = serde_json::to_string_pretty(self)?;
+        fs::write(path, text)
+    }
+}
+
+impl ItemData {
+    pub fn from_item(item: &CanvasItem, x: f64, y: f64, z: u32) -> Self {
+        Self {
+            id: item.id(),
+            x,
+            y,
+            angle: item.angle(),
+            scale_x: item.scale().0,
+            scale_y: item.scale().1,
+            label: item.label(),
+            color: item.color().map(|color| color.to_string()),
+            css_class: item.css_class(),
+            image: item.image_data(),
+            locked: item.is_locked(),
+            opacity: item.opacity(),
+            z,
+        }
+    }
+
+    /// Snapshots an item that is currently placed on a canvas.
+    pub fn capture(item: &CanvasItem) -> Option<Self> {
+        let canvas = item.parent()?.downcast::<Canvas>().ok()?;
+        let (x, y) = canvas.child_position(item);
+        let z = canvas.items().iter().position(|other| other == item)? as u32;
+        Some(Self::from_item(item, x, y, z))
+    }
+
+    pub fn to_item(&self) -> CanvasItem {
+        let item = CanvasItem::new();
+        item.set_id(&self.id);
+        self.restore(&item);
+        item
+    }
+
+    /// Copies label, angle and style onto `item` without touching its placement.
+    pub fn restore(&self, item: &CanvasItem) {
+        item.set_label(&self.label);
+        item.set_angle(self.angle);
+        item.set_scale(self.scale_x, self.scale_y);
+        if let Some(color) = self.color.as_deref().and_then(|c| RGBA::parse(c).ok()) {
+            item.set_color(color);
+        }
+        if let Some(class) = self.css_class.as_deref().filter(|c| is_valid_class_name(c)) {
+            item.set_css_class(class);
+        }
+        item.set_locked(self.locked);
+        item.set_opacity(self.opacity);
+        if self.image != item.image_data() {
+            item.set_image_data(self.image.as_deref());
+        }
+    }
+
+    /// Restores `item` completely, including its position and stacking order.
+    pub fn apply(&self, canvas: &Canvas, item: &CanvasItem) {
+        self.place(canvas, item);
+        self.restack(canvas, item);
+    }
+
+    /// Restores `item` and its position, leaving its stacking order alone.
+    pub fn place(&self, canvas: &Canvas, item: &CanvasItem) {
+        self.restore(item);
+        canvas.move_item(item, self.x, self.y);
+    }
+
+    /// Puts `item` back at its place in the stacking order.
+    pub fn restack(&self, canvas: &Canvas, item: &CanvasItem) {
+        let above = canvas
+            .items()
+            .into_iter()
+            .filter(|other| other != item)
+            .nth(self.z as usize);
+        item.insert_before(canvas, above.as_ref());
+    }
+}
+
+/// Upgrades a parsed document of any supported version to [`FORMAT_VERSION`].
+fn migrate(mut value: Value) -> Result<Value> {
+    loop {
+        let version = value
+            .get("version")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "missing document version"))?;
+
+        match version {
+            FORMAT_VERSION => return Ok(value),
+            // Version 2 added images.
+            1 => upgrade(&mut value, 2, &[("image", Value::Null)]),
+            // Version 3 added the lock.
+            2 => upgrade(&mut value, 3, &[("locked", Value::from(false))]),
+            // Version 4 added opacity.
+            3 => upgrade(&mut value, 4, &[("opacity", Value::from(1.))]),
+            // Version 5 added scaling.
+            4 => upgrade(
+                &mut value,
+                5,
+                &[("scale_x", Value::from(1.)), ("scale_y", Value::from(1.))],
+            ),
+            v if v > FORMAT_VERSION => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("document version {} is newer than this release supports", v),
+                ))
+            }
+            v => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("unknown document version {}", v),
+                ))
+            }
+        }
+    }
+}
+
+/// Gives every item the `defaults` for the fields it lacks and marks the
+/// document as `version`.
+fn upgrade(value: &mut Value, version: u64, defaults: &[(&str, Value)]) {
+    if let Some(items) = value.get_mut("items").and_then(Value::as_array_mut) {
+        for item in items.iter_mut().filter_map(Value::as_object_mut) {
+            for (key, default) in defaults {
+                item.entry(*key).or_insert_with(|| default.clone());
+            }
+        }
+    }
+    value["version"] = Value::from(version);
+}
+
+fn default_scale() -> f32 {
+    1.
+}
+
+fn default_opacity() -> f64 {
+    1.
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn current_version_is_unchanged() {
+        let value = json!({"version": FORMAT_VERSION, "items": []});
+        assert_eq!(migrate(value.clone()).unwrap(), value);
+    }
+
+    #[test]
+    fn rejects_missing_newer_and_unknown_versions() {
+        assert!(migrate(json!({"items": []})).is_err());
+        assert!(migrate(json!({"version": "1", "items": []})).is_err());
+        assert!(migrate(json!({"version": FORMAT_VERSION + 1, "items": []})).is_err());
+        assert!(migrate(json!({"version": 0, "items": []})).is_err());
+    }
+
+    #[test]
+    fn upgrades_version_1() {
+        let value = json!({
+            "version": 1,
+            "items": [{"id": "a", "x": 1.0, "y": 2.0, "angle": 0.0, "label": "A", "z": 0}],
+        });
+        let value = migrate(value).unwrap();
+        assert_eq!(value["version"], json!(FORMAT_VERSION));
+        assert_eq!(value["items"][0]["image"], Value::Null);
+        assert_eq!(value["items"][0]["label"], json!("A"));
+
+        let document: Document = serde_json::from_value(value).unwrap();
+        assert_eq!(document.items[0].image, None);
+    }
+
+    #[test]
+    fn upgrades_version_2() {
+        let value = json!({
+            "version": 2,
+            "items": [
+                {"id": "a", "x": 1.0, "y": 2.0, "angle": 0.0, "label": "A", "z": 0},
+                {"id": "b", "x": 1.0, "y": 2.0, "angle": 0.0, "label": "B", "locked": true, "z": 1},
+            ],
+        });
+        let value = migrate(value).unwrap();
+        assert_eq!(value["version"], json!(FORMAT_VERSION));
+        assert_eq!(value["items"][0]["locked"], json!(false));
+        assert_eq!(value["items"][1]["locked"], json!(true));
+    }
+
+    #[test]
+    fn upgrades_version_3() {
+        let value = json!({
+            "version": 3,
+            "items": [{"id": "a", "x": 1.0, "y": 2.0, "angle": 0.0, "label": "A", "z": 0}],
+        });
+        let document: Document = serde_json::from_value(migrate(value).unwrap()).unwrap();
+        assert_eq!(document.version, FORMAT_VERSION);
+        assert_eq!(document.items[0].opacity, 1.);
+    }
+
+    #[test]
+    fn upgrades_version_4() {
+        let value = json!({
+            "version": 4,
+            "items": [{"id": "a", "x": 1.0, "y": 2.0, "angle": 0.0, "label": "A", "z": 0}],
+        });
+        let document: Document = serde_json::from_value(migrate(value).unwrap()).unwrap();
+        assert_eq!(document.version, FORMAT_VERSION);
+        assert_eq!(
+            (document.items[0].scale_x, document.items[0].scale_y),
+            (1., 1.)
+        );
+    }
+
+    #[test]
+    fn loads_version_1_json() {
+        let json = br#"{"version": 1, "items": [
+            {"id": "a", "x": 1.0, "y": 2.0, "angle": 90.0, "label": "A",
+             "color": "rgb(255,0,0)", "css_class": "rainbow1", "z": 0}
+        ]}"#;
+        let document = Document::from_json(json).unwrap();
+        assert_eq!(document.version, FORMAT_VERSION);
+        let item = &document.items[0];
+        assert_eq!((item.x, item.y, item.angle), (1., 2., 90.));
+        assert_eq!(item.css_class.as_deref(), Some("rainbow1"));
+        assert_eq!((item.scale_x, item.scale_y), (1., 1.));
+        assert_eq!(
+            (item.image.as_deref(), item.locked, item.opacity),
+            (None, false, 1.)
+        );
+    }
+}
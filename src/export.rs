@@ -0,0 +1,539 @@
+//
+// export.rs
+// Copyright (C) 2022 gmg137 <gmg137 AT live.com>
+// Distributed under terms of the GPL-3.0-or-later license.
+//
+use gtk::gdk::{Texture, RGBA};
+use gtk::glib;
+use gtk::graphene::{Point, Rect};
+use gtk::prelude::*;
+use gtk::{gdk, gsk, pango};
+use gtk::{Label, Snapshot, Widget, WidgetPaintable};
+use std::fmt::Write;
+use std::fs;
+use std::io::{Error, Result};
+use std::path::Path;
+
+use crate::canvas::Canvas;
+use crate::canvas_item::{image_size, CanvasItem};
+use crate::document::Document;
+
+/// Colour stops shared by the `rainbow*` classes in `dnd.css`.
+const RAINBOW: [&str; 6] = ["red", "orange", "yellow", "green", "blue", "purple"];
+
+/// Corner radius used for the item rectangles in SVG output.
+const CORNER_RADIUS: f32 = 4.;
+
+#[derive(Debug, Clone)]
+pub enum Fill {
+    None,
+    Color(RGBA),
+    /// A `rainbow*` style class from `dnd.css`.
+    Gradient(String),
+}
+
+/// One canvas item reduced to what is needed to draw it.
+#[derive(Debug, Clone)]
+pub struct Shape {
+    pub cx: f32,
+    pub cy: f32,
+    pub width: f32,
+    pub height: f32,
+    pub angle: f32,
+    pub scale_x: f32,
+    pub scale_y: f32,
+    pub fill: Fill,
+    pub label: String,
+    pub font_family: String,
+    pub font_size: f32,
+    pub text_color: RGBA,
+    pub image: Option<Texture>,
+    /// Top padding of the frame and the gap between image and label.
+    pub padding: f32,
+    pub spacing: f32,
+    pub opacity: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Scene {
+    /// Top-left corner of the exported area in canvas coordinates.
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub shapes: Vec<Shape>,
+}
+
+impl Scene {
+    pub fn from_canvas(canvas: &Canvas) -> Self {
+        let shapes = canvas
+            .items()
+            .iter()
+            .map(|item| {
+                let (x, y) = canvas.child_position(item);
+                Shape::from_item(item, x as f32, y as f32)
+            })
+            .collect();
+        let area = export_area(canvas);
+        Self {
+            x: area.x(),
+            y: area.y(),
+            width: area.width(),
+            height: area.height(),
+            shapes,
+        }
+    }
+
+    /// Lays out the items of `document` without placing them in a window,
+    /// cropped to the box around them like an export of the canvas.
+    pub fn from_document(document: &Document) -> Self {
+        let mut items = document.items.clone();
+        items.sort_by_key(|data| data.z);
+        let shapes: Vec<Shape> = items
+            .iter()
+            .map(|data| Shape::from_item(&data.to_item(), data.x as f32, data.y as f32))
+            .collect();
+        let area = shapes
+            .iter()
+            .map(Shape::bounds)
+            .reduce(|union, bounds| union.union(&bounds))
+            .map_or_else(Rect::zero, |bounds| round_out(&bounds));
+        Self {
+            x: area.x(),
+            y: area.y(),
+            width: area.width(),
+            height: area.height(),
+            shapes,
+        }
+    }
+
+    /// Draws the scene the way the canvas would, for use where the widgets
+    /// themselves cannot be rendered.
+    pub fn snapshot(&self, snapshot: &Snapshot) {
+        let text = Label::new(None);
+        snapshot.save();
+        snapshot.translate(&Point::new(-self.x, -self.y));
+        for shape in &self.shapes {
+            shape.snapshot(snapshot, &text);
+        }
+        snapshot.restore();
+    }
+
+    pub fn to_svg(&self) -> String {
+        let mut svg = String::new();
+        writeln!(svg, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+        writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="{x} {y} {w} {h}">"#,
+            x = self.x,
+            y = self.y,
+            w = self.width,
+            h = self.height
+        )
+        .unwrap();
+
+        let mut gradients: Vec<&str> = self
+            .shapes
+            .iter()
+            .filter_map(|shape| match &shape.fill {
+                Fill::Gradient(class) if gradient_angle(class).is_some() => Some(class.as_str()),
+                _ => None,
+            })
+            .collect();
+        gradients.sort_unstable();
+        gradients.dedup();
+        if !gradients.is_empty() {
+            writeln!(svg, "  <defs>").unwrap();
+            for class in gradients {
+                write_gradient(&mut svg, class);
+            }
+            writeln!(svg, "  </defs>").unwrap();
+        }
+
+        for shape in &self.shapes {
+            shape.write_svg(&mut svg);
+        }
+        writeln!(svg, "</svg>").unwrap();
+        svg
+    }
+}
+
+impl Shape {
+    pub fn from_item(item: &CanvasItem, x: f32, y: f32) -> Self {
+        let label = item.label_widget();
+        let (width, height) = item.size();
+        let (width, height) = (width as f32, height as f32);
+        let (scale_x, scale_y) = item.scale();
+        let r = ((width * scale_x).powi(2) + (height * scale_y).powi(2)).sqrt() / 2.;
+
+        let fill = match (item.color(), item.css_class()) {
+            (Some(color), _) => Fill::Color(color),
+            (None, Some(class)) => Fill::Gradient(class),
+            (None, None) => Fill::None,
+        };
+
+        let font = label.pango_context().font_description().unwrap_or_default();
+        let (font_family, font_size) = font_metrics(&font);
+        let frame = label
+            .parent()
+            .and_then(|frame| frame.downcast::<gtk::Box>().ok());
+        let (padding, spacing) = frame.map_or((0., 0.), |frame| {
+            let padding = frame.style_context().padding();
+            (padding.top() as f32, frame.spacing() as f32)
+        });
+
+        Self {
+            cx: x + r,
+            cy: y + r,
+            width,
+            height,
+            angle: item.angle(),
+            scale_x,
+            scale_y,
+            fill,
+            label: item.label(),
+            font_family,
+            font_size,
+            text_color: label.style_context().color(),
+            image: item.texture(),
+            padding,
+            spacing,
+            opacity: item.opacity() as f32,
+        }
+    }
+
+    /// Bounding box of the rotated and scaled shape.
+    fn bounds(&self) -> Rect {
+        let (sin, cos) = self.angle.to_radians().sin_cos();
+        let (width, height) = (self.width * self.scale_x, self.height * self.scale_y);
+        let half_width = ((width * cos).abs() + (height * sin).abs()) / 2.;
+        let half_height = ((width * sin).abs() + (height * cos).abs()) / 2.;
+        Rect::new(
+            self.cx - half_width,
+            self.cy - half_height,
+            2. * half_width,
+            2. * half_height,
+        )
+    }
+
+    /// Bounds of the image relative to the centre of the shape, and how far
+    /// the label is pushed down to make room for it.
+    fn image_layout(&self) -> Option<(Rect, f32)> {
+        let (width, height) = image_size(self.image.as_ref()?);
+        let bounds = Rect::new(-width / 2., -self.height / 2. + self.padding, width, height);
+        Some((bounds, (height + self.spacing) / 2.))
+    }
+
+    fn snapshot(&self, snapshot: &Snapshot, text: &Label) {
+        snapshot.save();
+        snapshot.translate(&Point::new(self.cx, self.cy));
+        snapshot.rotate(self.angle);
+        snapshot.scale(self.scale_x, self.scale_y);
+        snapshot.push_opacity(self.opacity as f64);
+
+        let bounds = Rect::new(-self.width / 2., -self.height / 2., self.width, self.height);
+        snapshot.push_rounded_clip(&gsk::RoundedRect::from_rect(bounds, CORNER_RADIUS));
+        match &self.fill {
+            Fill::None => {}
+            Fill::Color(color) => snapshot.append_color(color, &bounds),
+            Fill::Gradient(class) => {
+                if let Some(angle) = gradient_angle(class) {
+                    let (start, end) = gradient_line(angle, self.width, self.height);
+                    let stops: Vec<gsk::ColorStop> = RAINBOW
+                        .iter()
+                        .enumerate()
+                        .map(|(i, color)| {
+                            let offset = i as f32 / (RAINBOW.len() - 1) as f32;
+                            gsk::ColorStop::new(offset, RGBA::parse(color).unwrap())
+                        })
+                        .collect();
+                    snapshot.append_linear_gradient(&bounds, &start, &end, &stops);
+                }
+            }
+        }
+        snapshot.pop();
+
+        let mut text_offset = 0.;
+        if let (Some(texture), Some((bounds, offset))) = (&self.image, self.image_layout()) {
+            snapshot.append_texture(texture, &bounds);
+            text_offset = offset;
+        }
+
+        let layout = text.create_pango_layout(Some(&self.label));
+        let mut font = pango::FontDescription::new();
+        font.set_family(&self.font_family);
+        font.set_absolute_size(self.font_size as f64 * pango::SCALE as f64);
+        layout.set_font_description(Some(&font));
+        let (width, height) = layout.pixel_size();
+        snapshot.translate(&Point::new(
+            -width as f32 / 2.,
+            text_offset - height as f32 / 2.,
+        ));
+        snapshot.append_layout(&layout, &self.text_color);
+
+        snapshot.pop();
+        snapshot.restore();
+    }
+
+    fn write_svg(&self, svg: &mut String) {
+        let fill = match &self.fill {
+            Fill::None => r#"fill="none""#.to_string(),
+            Fill::Color(color) => svg_paint("fill", color),
+            Fill::Gradient(class) if gradient_angle(class).is_some() => {
+                format!(r#"fill="url(#{})""#, class)
+            }
+            Fill::Gradient(_) => r#"fill="none""#.to_string(),
+        };
+        writeln!(
+            svg,
+            r#"  <g transform="translate({} {}) rotate({}) scale({} {})" opacity="{}">"#,
+            self.cx, self.cy, self.angle, self.scale_x, self.scale_y, self.opacity
+        )
+        .unwrap();
+        writeln!(
+            svg,
+            r#"    <rect x="{}" y="{}" width="{}" height="{}" rx="{r}" ry="{r}" {}/>"#,
+            -self.width / 2.,
+            -self.height / 2.,
+            self.width,
+            self.height,
+            fill,
+            r = CORNER_RADIUS
+        )
+        .unwrap();
+
+        let mut text_offset = 0.;
+        if let (Some(texture), Some((bounds, offset))) = (&self.image, self.image_layout()) {
+            writeln!(
+                svg,
+                r#"    <image x="{}" y="{}" width="{}" height="{}" href="data:image/png;base64,{}"/>"#,
+                bounds.x(),
+                bounds.y(),
+                bounds.width(),
+                bounds.height(),
+                glib::base64_encode(&texture.save_to_png_bytes())
+            )
+            .unwrap();
+            text_offset = offset;
+        }
+
+        writeln!(
+            svg,
+            r#"    <text x="0" y="{}" text-anchor="middle" dominant-baseline="central" font-family="{}" font-size="{}px" {}>{}</text>"#,
+            text_offset,
+            escape(&self.font_family),
+            self.font_size,
+            svg_paint("fill", &self.text_color),
+            escape(&self.label)
+        )
+        .unwrap();
+        writeln!(svg, "  </g>").unwrap();
+    }
+}
+
+/// Renders the widget tree below `widget` with the cairo renderer, so the
+/// export also works where no GL context is available. A canvas is cropped
+/// to its items wherever they are, without the grid. Selection outlines,
+/// handles and knobs are left out.
+pub fn export_png(widget: &impl IsA<Widget>, scale: f64, path: &Path) -> Result<()> {
+    let snapshot = Snapshot::new();
+    snapshot.scale(scale as f32, scale as f32);
+    let (width, height) = match widget.dynamic_cast_ref::<Canvas>() {
+        Some(canvas) => {
+            let area = export_area(canvas);
+            snapshot.translate(&Point::new(-area.x(), -area.y()));
+            without_selection(&canvas.items(), || canvas.snapshot_items(&snapshot));
+            (area.width() as f64, area.height() as f64)
+        }
+        None => {
+            let (width, height) = (widget.width() as f64, widget.height() as f64);
+            let paintable = WidgetPaintable::new(Some(widget));
+            without_selection(&item_of(widget), || {
+                paintable.snapshot(snapshot.upcast_ref::<gdk::Snapshot>(), width, height)
+            });
+            (width, height)
+        }
+    };
+
+    render_png(&snapshot, width * scale, height * scale, path)
+}
+
+/// Runs `f` with `items` drawn as if they were not selected.
+fn without_selection<T>(items: &[CanvasItem], f: impl FnOnce() -> T) -> T {
+    let selected: Vec<&CanvasItem> = items.iter().filter(|item| item.is_selected()).collect();
+    for item in &selected {
+        item.set_selected(false);
+    }
+    let result = f();
+    for item in &selected {
+        item.set_selected(true);
+    }
+    result
+}
+
+/// The item `widget` is, if it is one.
+fn item_of(widget: &impl IsA<Widget>) -> Vec<CanvasItem> {
+    widget
+        .dynamic_cast_ref::<CanvasItem>()
+        .into_iter()
+        .cloned()
+        .collect()
+}
+
+/// Part of `canvas` that gets exported: the box around the items, rounded
+/// out to whole pixels, or what is on screen while the canvas is empty.
+fn export_area(canvas: &Canvas) -> Rect {
+    let area = canvas
+        .items_bounds()
+        .unwrap_or_else(|| canvas.visible_area());
+    round_out(&area)
+}
+
+/// Smallest rectangle on whole pixels that contains `rect`.
+fn round_out(rect: &Rect) -> Rect {
+    let (x, y) = (rect.x().floor(), rect.y().floor());
+    let right = (rect.x() + rect.width()).ceil();
+    let bottom = (rect.y() + rect.height()).ceil();
+    Rect::new(x, y, right - x, bottom - y)
+}
+
+pub fn export_svg(canvas: &Canvas, path: &Path) -> Result<()> {
+    fs::write(path, Scene::from_canvas(canvas).to_svg())
+}
+
+/// Renders `document` to `path` without a window, as SVG when the file name
+/// ends in `.svg` and as PNG otherwise. The image starts at the top-left
+/// corner of the items and is as large as they are unless `width` or
+/// `height` say otherwise.
+pub fn render_document(
+    document: &Document,
+    width: Option<f32>,
+    height: Option<f32>,
+    path: &Path,
+) -> Result<()> {
+    let mut scene = Scene::from_document(document);
+    scene.width = width.unwrap_or(scene.width).max(1.);
+    scene.height = height.unwrap_or(scene.height).max(1.);
+    let is_svg = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("svg"));
+    if is_svg {
+        return fs::write(path, scene.to_svg());
+    }
+
+    let snapshot = Snapshot::new();
+    scene.snapshot(&snapshot);
+    render_png(&snapshot, scene.width as f64, scene.height as f64, path)
+}
+
+fn render_png(snapshot: &Snapshot, width: f64, height: f64, path: &Path) -> Result<()> {
+    render_texture(snapshot, width, height)?
+        .save_to_png(path)
+        .map_err(|err| Error::other(err.to_string()))
+}
+
+/// Renders `document` the way [`render_document`] does and encodes it as PNG.
+pub fn document_to_png(document: &Document) -> Result<glib::Bytes> {
+    let scene = Scene::from_document(document);
+    let snapshot = Snapshot::new();
+    scene.snapshot(&snapshot);
+    let (width, height) = (scene.width.max(1.) as f64, scene.height.max(1.) as f64);
+    Ok(render_texture(&snapshot, width, height)?.save_to_png_bytes())
+}
+
+/// Renders `snapshot` into a `width` by `height` texture. Nothing drawn
+/// gives a transparent texture of that size.
+fn render_texture(snapshot: &Snapshot, width: f64, height: f64) -> Result<gdk::Texture> {
+    let viewport = Rect::new(0., 0., width as f32, height as f32);
+    let node = snapshot
+        .to_node()
+        .unwrap_or_else(|| gsk::ColorNode::new(&RGBA::new(0., 0., 0., 0.), &viewport).upcast());
+
+    let renderer = gsk::CairoRenderer::new();
+    renderer
+        .realize(None)
+        .map_err(|err| Error::other(err.to_string()))?;
+    let texture = renderer.render_texture(&node, Some(&viewport));
+    renderer.unrealize();
+    Ok(texture)
+}
+
+/// Direction of the linear gradient behind a `rainbow*` class, in CSS degrees.
+/// The animated classes use their first keyframe.
+fn gradient_angle(class: &str) -> Option<f32> {
+    match class {
+        "rainbow1" | "rainbow3" => Some(140.),
+        "rainbow2" => Some(0.),
+        _ => None,
+    }
+}
+
+/// Start and end point of a CSS `linear-gradient` at `angle` degrees across a
+/// box of the given size centred on the origin.
+fn gradient_line(angle: f32, width: f32, height: f32) -> (Point, Point) {
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let half = (width * sin.abs() + height * cos.abs()) / 2.;
+    (
+        Point::new(-sin * half, cos * half),
+        Point::new(sin * half, -cos * half),
+    )
+}
+
+fn write_gradient(svg: &mut String, class: &str) {
+    let angle = gradient_angle(class).unwrap().to_radians();
+    let (dx, dy) = (angle.sin() / 2., -angle.cos() / 2.);
+    writeln!(
+        svg,
+        r#"    <linearGradient id="{}" x1="{}" y1="{}" x2="{}" y2="{}">"#,
+        class,
+        0.5 - dx,
+        0.5 - dy,
+        0.5 + dx,
+        0.5 + dy
+    )
+    .unwrap();
+    for (i, color) in RAINBOW.iter().enumerate() {
+        writeln!(
+            svg,
+            r#"      <stop offset="{}" stop-color="{}"/>"#,
+            i as f32 / (RAINBOW.len() - 1) as f32,
+            color
+        )
+        .unwrap();
+    }
+    writeln!(svg, "    </linearGradient>").unwrap();
+}
+
+/// Family and pixel size of `font`, assuming 96 dpi for point sizes.
+fn font_metrics(font: &pango::FontDescription) -> (String, f32) {
+    let family = font
+        .family()
+        .map(|family| family.to_string())
+        .unwrap_or_else(|| "sans-serif".to_string());
+    let mut size = font.size() as f32 / pango::SCALE as f32;
+    if !font.is_size_absolute() {
+        size *= 96. / 72.;
+    }
+    if size <= 0. {
+        size = 14.;
+    }
+    (family, size)
+}
+
+fn svg_paint(attribute: &str, color: &RGBA) -> String {
+    format!(
+        r##"{a}="#{:02x}{:02x}{:02x}" {a}-opacity="{}""##,
+        (color.red() * 255.).round() as u8,
+        (color.green() * 255.).round() as u8,
+        (color.blue() * 255.).round() as u8,
+        color.alpha(),
+        a = attribute
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
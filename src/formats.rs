@@ -0,0 +1,378 @@
+//
+// formats.rs
+// Copyright (C) 2022 gmg137 <gmg137 AT live.com>
+// Distributed under terms of the GPL-3.0-or-later license.
+//
+use gtk::gdk::{ContentDeserializer, ContentFormats, ContentFormatsBuilder, ContentProvider, RGBA};
+use gtk::glib::clone;
+use gtk::prelude::*;
+use gtk::subclass::prelude::*;
+use gtk::{gdk, gio, glib};
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::future::Future;
+use std::pin::Pin;
+
+use crate::canvas_item::CanvasItem;
+use crate::document::Document;
+use crate::export::document_to_png;
+
+/// Colour format used by GIMP, Inkscape and GTK 3: four native-endian `u16`
+/// channels in the order red, green, blue, alpha.
+pub const COLOR_MIME_TYPE: &str = "application/x-color";
+
+pub const TEXT_MIME_TYPE: &str = "text/plain;charset=utf-8";
+
+/// A style dragged from a `CssButton`, serialized as JSON.
+pub const STYLE_MIME_TYPE: &str = "application/x-gtk4-dnd-style";
+
+/// Canvas items, serialized as a JSON [`Document`].
+pub const ITEMS_MIME_TYPE: &str = "application/x-gtk4-dnd-items";
+
+/// A CSS class to put on a canvas item. Only the name is sent; the rules
+/// come from the receiver's own stylesheet.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, glib::Boxed)]
+#[boxed_type(name = "Gtk4DndStyle")]
+pub struct Style {
+    pub class: String,
+}
+
+impl Style {
+    pub fn new(class: &str) -> Self {
+        Self {
+            class: class.to_string(),
+        }
+    }
+
+    /// Offers the style in-process as a `Style` value and to other instances
+    /// as `application/x-gtk4-dnd-style`.
+    pub fn provider(&self) -> ContentProvider {
+        let json = serde_json::to_vec(self).unwrap();
+        ContentProvider::new_union(&[
+            ContentProvider::for_value(&self.to_value()),
+            ContentProvider::for_bytes(STYLE_MIME_TYPE, &glib::Bytes::from_owned(json)),
+        ])
+    }
+}
+
+/// Whether `class` is a plain CSS identifier that is safe to add as a class.
+pub fn is_valid_class_name(class: &str) -> bool {
+    let name = class.strip_prefix('-').unwrap_or(class);
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c == '_' || c.is_ascii_alphabetic() => {}
+        _ => return false,
+    }
+    chars.all(|c| c == '_' || c == '-' || c.is_ascii_alphanumeric())
+}
+
+/// Registers the conversions GDK needs to hand colours, styles and items
+/// dropped from other applications to typed drop targets. Call once after GTK is
+/// initialized.
+pub fn register_formats() {
+    gdk::content_register_deserializer(
+        COLOR_MIME_TYPE,
+        RGBA::static_type(),
+        |deserializer: &ContentDeserializer, _: &mut Option<()>| {
+            deserialize_with(deserializer, |bytes| {
+                color_from_x_color(bytes).map(|color| color.to_value())
+            });
+        },
+    );
+    gdk::content_register_deserializer(
+        ITEMS_MIME_TYPE,
+        Document::static_type(),
+        |deserializer: &ContentDeserializer, _: &mut Option<()>| {
+            deserialize_with(deserializer, |bytes| {
+                Document::from_json(bytes)
+                    .ok()
+                    .map(|document| document.to_value())
+            });
+        },
+    );
+    gdk::content_register_deserializer(
+        STYLE_MIME_TYPE,
+        Style::static_type(),
+        |deserializer: &ContentDeserializer, _: &mut Option<()>| {
+            deserialize_with(deserializer, |bytes| {
+                serde_json::from_slice::<Style>(bytes)
+                    .ok()
+                    .map(|style| style.to_value())
+            });
+        },
+    );
+}
+
+/// Reads the whole input of `deserializer` and completes it with the value
+/// `parse` produces, or with an error when `parse` rejects the data.
+fn deserialize_with<F>(deserializer: &ContentDeserializer, parse: F)
+where
+    F: FnOnce(&[u8]) -> Option<glib::Value> + 'static,
+{
+    let output = gio::MemoryOutputStream::new_resizable();
+    output.splice_async(
+        &deserializer.input_stream(),
+        gio::OutputStreamSpliceFlags::CLOSE_SOURCE | gio::OutputStreamSpliceFlags::CLOSE_TARGET,
+        deserializer.priority(),
+        deserializer.cancellable().as_ref(),
+        clone!(@strong deserializer, @strong output => move |result| {
+            if let Err(err) = result {
+                deserializer.return_error(err);
+                return;
+            }
+            match parse(&output.steal_as_bytes()) {
+                Some(value) => {
+                    deserializer.set_value(value);
+                    deserializer.return_success();
+                }
+                None => deserializer.return_error(glib::Error::new(
+                    gio::IOErrorEnum::InvalidData,
+                    &format!("invalid {} data", deserializer.mime_type()),
+                )),
+            }
+        }),
+    );
+}
+
+/// Offers `color` in-process as `RGBA` and to other applications as
+/// `application/x-color` and `#rrggbb` text.
+pub fn color_provider(color: &RGBA) -> ContentProvider {
+    ContentProvider::new_union(&[
+        ContentProvider::for_value(&color.to_value()),
+        ContentProvider::for_bytes(
+            COLOR_MIME_TYPE,
+            &glib::Bytes::from_owned(color_to_x_color(color)),
+        ),
+        ContentProvider::for_bytes(
+            TEXT_MIME_TYPE,
+            &glib::Bytes::from_owned(color_to_hex(color)),
+        ),
+        ContentProvider::for_bytes("text/plain", &glib::Bytes::from_owned(color_to_hex(color))),
+    ])
+}
+
+/// Offers a dragged canvas item in-process as the widget itself and to other
+/// applications as JSON, as the labels of `items` and as a PNG of them.
+/// `items` are the items that move with it, bottom to top. The data is only
+/// produced when a drop target asks for it.
+pub fn items_provider(item: &CanvasItem, items: &[CanvasItem]) -> ContentProvider {
+    ItemsContent::new(item, items).upcast()
+}
+
+/// Marks the items offered by `provider` as taken by a canvas, so a move
+/// may remove them from where they were dragged from.
+pub fn take_items(provider: &ContentProvider) {
+    if let Some(content) = provider.downcast_ref::<ItemsContent>() {
+        content.imp().taken.set(true);
+    }
+}
+
+/// Whether a canvas, in this process or another, took the items offered by
+/// `provider`. Other applications only ever get a copy.
+pub fn items_taken(provider: &ContentProvider) -> bool {
+    provider
+        .downcast_ref::<ItemsContent>()
+        .is_some_and(|content| content.imp().taken.get())
+}
+
+glib::wrapper! {
+    struct ItemsContent(ObjectSubclass<ExItemsContent>) @extends ContentProvider;
+}
+
+impl ItemsContent {
+    fn new(item: &CanvasItem, items: &[CanvasItem]) -> Self {
+        let content: Self = glib::Object::new(&[]).expect("Failed to create an ItemsContent");
+        content.imp().item.replace(Some(item.clone()));
+        content.imp().items.replace(items.to_vec());
+        content
+    }
+}
+
+#[derive(Debug, Default)]
+struct ExItemsContent {
+    item: RefCell<Option<CanvasItem>>,
+    items: RefCell<Vec<CanvasItem>>,
+    taken: Cell<bool>,
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for ExItemsContent {
+    const NAME: &'static str = "Gtk4DndItemsContent";
+    type Type = ItemsContent;
+    type ParentType = ContentProvider;
+}
+
+impl ObjectImpl for ExItemsContent {}
+
+impl ContentProviderImpl for ExItemsContent {
+    fn formats(&self, _provider: &Self::Type) -> ContentFormats {
+        ContentFormatsBuilder::new()
+            .add_type(gtk::Widget::static_type())
+            .add_mime_type(ITEMS_MIME_TYPE)
+            .add_mime_type(TEXT_MIME_TYPE)
+            .add_mime_type("text/plain")
+            .add_mime_type("image/png")
+            .build()
+    }
+
+    fn value(&self, provider: &Self::Type, type_: glib::Type) -> Result<glib::Value, glib::Error> {
+        match self.item.borrow().as_ref() {
+            Some(item) if type_ == gtk::Widget::static_type() => {
+                Ok(item.upcast_ref::<gtk::Widget>().to_value())
+            }
+            _ => self.parent_value(provider, type_),
+        }
+    }
+
+    fn write_mime_type_future(
+        &self,
+        _provider: &Self::Type,
+        mime_type: &str,
+        stream: &gio::OutputStream,
+        io_priority: glib::Priority,
+    ) -> Pin<Box<dyn Future<Output = Result<(), glib::Error>> + 'static>> {
+        // Only another instance of the canvas asks for the items format.
+        if mime_type == ITEMS_MIME_TYPE {
+            self.taken.set(true);
+        }
+        let data = self.data(mime_type);
+        let stream = stream.clone();
+        Box::pin(async move {
+            stream
+                .write_all_future(data?, io_priority)
+                .await
+                .map_err(|(_, err)| err)?;
+            Ok(())
+        })
+    }
+}
+
+impl ExItemsContent {
+    fn data(&self, mime_type: &str) -> Result<Vec<u8>, glib::Error> {
+        let items = self.items.borrow();
+        match mime_type {
+            ITEMS_MIME_TYPE => Ok(serde_json::to_vec(&Document::from_items(&items)).unwrap()),
+            TEXT_MIME_TYPE | "text/plain" => Ok(labels(&items).into_bytes()),
+            "image/png" => document_to_png(&Document::from_items(&items))
+                .map(|png| png.to_vec())
+                .map_err(|err| glib::Error::new(gio::IOErrorEnum::Failed, &err.to_string())),
+            _ => Err(glib::Error::new(
+                gio::IOErrorEnum::NotSupported,
+                &format!("cannot provide {}", mime_type),
+            )),
+        }
+    }
+}
+
+/// Offers `items` for the clipboard as a `Document`, as JSON and as their
+/// labels, one per line.
+pub fn clipboard_provider(items: &[CanvasItem]) -> ContentProvider {
+    let mut providers = vec![ContentProvider::for_value(
+        &Document::from_items(items).to_value(),
+    )];
+    providers.append(&mut items_content(items));
+    ContentProvider::new_union(&providers)
+}
+
+fn items_content(items: &[CanvasItem]) -> Vec<ContentProvider> {
+    let json = serde_json::to_vec(&Document::from_items(items)).unwrap();
+    let text = labels(items);
+    vec![
+        ContentProvider::for_bytes(ITEMS_MIME_TYPE, &glib::Bytes::from_owned(json)),
+        ContentProvider::for_bytes(TEXT_MIME_TYPE, &glib::Bytes::from(text.as_bytes())),
+        ContentProvider::for_bytes("text/plain", &glib::Bytes::from_owned(text)),
+    ]
+}
+
+/// The labels of `items`, one per line.
+fn labels(items: &[CanvasItem]) -> String {
+    items
+        .iter()
+        .map(|item| item.label())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn color_to_x_color(color: &RGBA) -> Vec<u8> {
+    [color.red(), color.green(), color.blue(), color.alpha()]
+        .iter()
+        .flat_map(|channel| ((channel.clamp(0., 1.) * 65535.).round() as u16).to_ne_bytes())
+        .collect()
+}
+
+pub fn color_from_x_color(bytes: &[u8]) -> Option<RGBA> {
+    if bytes.len() < 8 {
+        return None;
+    }
+    let channel = |i: usize| u16::from_ne_bytes([bytes[2 * i], bytes[2 * i + 1]]) as f32 / 65535.;
+    Some(RGBA::new(channel(0), channel(1), channel(2), channel(3)))
+}
+
+pub fn color_to_hex(color: &RGBA) -> String {
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        (color.red() * 255.).round() as u8,
+        (color.green() * 255.).round() as u8,
+        (color.blue() * 255.).round() as u8
+    )
+}
+
+/// Parses text such as `#ff8800` or `#f80` dropped from another application.
+pub fn parse_hex_color(text: &str) -> Option<RGBA> {
+    let text = text.trim();
+    if !text.starts_with('#') {
+        return None;
+    }
+    RGBA::parse(text).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn x_color_round_trip() {
+        let color = RGBA::new(1., 0.5, 0., 0.25);
+        let bytes = color_to_x_color(&color);
+        assert_eq!(bytes.len(), 8);
+        let back = color_from_x_color(&bytes).unwrap();
+        for (a, b) in [
+            (color.red(), back.red()),
+            (color.green(), back.green()),
+            (color.blue(), back.blue()),
+            (color.alpha(), back.alpha()),
+        ] {
+            assert!((a - b).abs() < 1. / 65535., "{} != {}", a, b);
+        }
+    }
+
+    #[test]
+    fn x_color_clamps_and_rejects_short_data() {
+        let bytes = color_to_x_color(&RGBA::new(2., -1., 0., 1.));
+        let color = color_from_x_color(&bytes).unwrap();
+        assert_eq!((color.red(), color.green()), (1., 0.));
+        assert!(color_from_x_color(&bytes[..7]).is_none());
+    }
+
+    #[test]
+    fn hex_colors() {
+        let color = parse_hex_color(" #ff8800\n").unwrap();
+        assert_eq!((color.red(), color.blue()), (1., 0.));
+        assert!((color.green() - 136. / 255.).abs() < 1e-6);
+        assert_eq!(parse_hex_color("#f80"), Some(color));
+        assert_eq!(parse_hex_color(&color_to_hex(&color)), Some(color));
+        assert!(parse_hex_color("ff8800").is_none());
+        assert!(parse_hex_color("red").is_none());
+        assert!(parse_hex_color("#zzz").is_none());
+    }
+
+    #[test]
+    fn class_names() {
+        for valid in ["rainbow1", "_private", "-vendor", "a-b_c", "X"] {
+            assert!(is_valid_class_name(valid), "{}", valid);
+        }
+        for invalid in ["", "-", "1abc", "--x", "a b", "a.b", "a{}", "é"] {
+            assert!(!is_valid_class_name(invalid), "{}", invalid);
+        }
+    }
+}
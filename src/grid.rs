@@ -0,0 +1,142 @@
+//
+// grid.rs
+// Copyright (C) 2022 gmg137 <gmg137 AT live.com>
+// Distributed under terms of the GPL-3.0-or-later license.
+//
+use gtk::gdk::{Key, ModifierType, RGBA};
+use gtk::glib::clone;
+use gtk::graphene::Rect;
+use gtk::prelude::*;
+use gtk::{glib, EventControllerKey, Snapshot};
+
+use crate::canvas::Canvas;
+use crate::canvas_item::CanvasItem;
+use crate::document::ItemData;
+use crate::history::record_changes;
+
+/// Distance the arrow keys move items without a grid, and with Shift.
+const NUDGE: f64 = 1.;
+const NUDGE_LARGE: f64 = 10.;
+
+/// Draws the grid lines behind the items when the grid is enabled.
+pub fn draw_grid(canvas: &Canvas, snapshot: &Snapshot) {
+    if !canvas.has_grid() {
+        return;
+    }
+    let size = canvas.grid_size() as f32;
+    let area = canvas.visible_area();
+    let (right, bottom) = (area.x() + area.width(), area.y() + area.height());
+    let color = RGBA::new(0.5, 0.5, 0.5, 0.25);
+
+    for x in grid_lines(area.x(), right, size) {
+        let line = Rect::new(x.round(), area.y(), 1., area.height());
+        snapshot.append_color(&color, &line);
+    }
+    for y in grid_lines(area.y(), bottom, size) {
+        let line = Rect::new(area.x(), y.round(), area.width(), 1.);
+        snapshot.append_color(&color, &line);
+    }
+}
+
+/// Positions of the grid lines `size` apart from `start` up to `end`.
+fn grid_lines(start: f32, end: f32, size: f32) -> impl Iterator<Item = f32> {
+    let first = (start / size).ceil();
+    (0..)
+        .map(move |i| (first + i as f32) * size)
+        .take_while(move |line| *line < end)
+}
+
+/// Rounds `value` to the nearest multiple of `size`.
+pub fn snap_to_grid(value: f64, size: f64) -> f64 {
+    (value / size).round() * size
+}
+
+/// Whether the grid applies to an action done with `state` held. Alt
+/// turns snapping off for the duration of a drag or key press.
+pub fn snaps(canvas: &Canvas, state: ModifierType) -> bool {
+    canvas.has_grid() && !state.contains(ModifierType::ALT_MASK)
+}
+
+/// Adjusts `(x, y)`, a proposed position for `item`, so the top-left corner
+/// of what the item draws lands on a grid point.
+pub fn snap_item(canvas: &Canvas, item: &CanvasItem, x: f64, y: f64) -> (f64, f64) {
+    let (dx, dy) = item
+        .bounds(item)
+        .map_or((0., 0.), |bounds| (bounds.x() as f64, bounds.y() as f64));
+    let (x, y) = canvas.snap(x + dx, y + dy);
+    (x - dx, y - dy)
+}
+
+/// Moves the selected items with the arrow keys. With the grid enabled the
+/// topmost selected item snaps to the grid and steps one grid cell, and the
+/// others follow it.
+pub fn add_nudge_keys(canvas: &Canvas) {
+    canvas.set_focusable(true);
+    let keys = EventControllerKey::new();
+    keys.connect_key_pressed(
+        clone!(@weak canvas => @default-return glib::signal::Inhibit(false), move |_, key, _, state| {
+            let (dx, dy) = match key {
+                Key::Left => (-1., 0.),
+                Key::Right => (1., 0.),
+                Key::Up => (0., -1.),
+                Key::Down => (0., 1.),
+                _ => return glib::signal::Inhibit(false),
+            };
+            let mut items = canvas.selected_items();
+            items.retain(|item| !item.is_locked());
+            let lead = match items.last() {
+                Some(item) => item.clone(),
+                None => return glib::signal::Inhibit(false),
+            };
+
+            let (x, y) = canvas.child_position(&lead);
+            let (to_x, to_y) = if snaps(&canvas, state) {
+                let (snapped_x, snapped_y) = snap_item(&canvas, &lead, x, y);
+                let size = canvas.grid_size();
+                (snapped_x + dx * size, snapped_y + dy * size)
+            } else {
+                let step = if state.contains(ModifierType::SHIFT_MASK) {
+                    NUDGE_LARGE
+                } else {
+                    NUDGE
+                };
+                (x + dx * step, y + dy * step)
+            };
+
+            items.reverse();
+            let before = items.iter().map(ItemData::capture).collect();
+            for item in &items {
+                let (item_x, item_y) = canvas.child_position(item);
+                canvas.move_item(item, item_x + to_x - x, item_y + to_y - y);
+            }
+            record_changes(&canvas, &items, before, Some("nudge"));
+            glib::signal::Inhibit(true)
+        }),
+    );
+    canvas.add_controller(&keys);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snaps_to_the_nearest_multiple() {
+        assert_eq!(snap_to_grid(0., 20.), 0.);
+        assert_eq!(snap_to_grid(9.9, 20.), 0.);
+        assert_eq!(snap_to_grid(10.1, 20.), 20.);
+        assert_eq!(snap_to_grid(47., 20.), 40.);
+        assert_eq!(snap_to_grid(-11., 20.), -20.);
+        assert_eq!(snap_to_grid(-9., 20.), 0.);
+        assert_eq!(snap_to_grid(7.4, 2.5), 7.5);
+    }
+
+    #[test]
+    fn lines_cover_the_range() {
+        let lines: Vec<f32> = grid_lines(0., 50., 20.).collect();
+        assert_eq!(lines, [0., 20., 40.]);
+        let lines: Vec<f32> = grid_lines(-25., 20., 10.).collect();
+        assert_eq!(lines, [-20., -10., 0., 10.]);
+        assert_eq!(grid_lines(5., 9., 10.).count(), 0);
+    }
+}
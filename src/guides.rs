@@ -0,0 +1,339 @@
+//
+// guides.rs
+// Copyright (C) 2022 gmg137 <gmg137 AT live.com>
+// Distributed under terms of the GPL-3.0-or-later license.
+//
+use gtk::gdk::{ModifierType, RGBA};
+use gtk::graphene::Rect;
+use gtk::prelude::*;
+use gtk::Snapshot;
+
+use crate::canvas::Canvas;
+use crate::canvas_item::CanvasItem;
+use crate::grid::{snap_item, snaps};
+
+/// How close, in pixels, an edge has to come to a guide to snap to it.
+const SNAP_DISTANCE: f64 = 6.;
+
+/// Length of the ticks closing the ends of an equal-spacing indicator.
+const TICK: f32 = 6.;
+
+/// A line drawn over the canvas while an item is dragged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Guide {
+    /// Edges or centres line up at this x.
+    Vertical(f64),
+    /// Edges or centres line up at this y.
+    Horizontal(f64),
+    /// The item sits midway between two others on the x axis; the two equal
+    /// gaps are drawn at height `y`.
+    HorizontalGaps { y: f64, gaps: [(f64, f64); 2] },
+    /// The item sits midway between two others on the y axis.
+    VerticalGaps { x: f64, gaps: [(f64, f64); 2] },
+}
+
+/// Extent of a rectangle along one axis.
+#[derive(Debug, Clone, Copy)]
+struct Span {
+    start: f64,
+    end: f64,
+}
+
+impl Span {
+    fn mid(&self) -> f64 {
+        (self.start + self.end) / 2.
+    }
+
+    fn shift(&self, by: f64) -> Self {
+        Self {
+            start: self.start + by,
+            end: self.end + by,
+        }
+    }
+
+    fn overlaps(&self, other: &Span) -> bool {
+        self.start < other.end && other.start < self.end
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bounds {
+    x: Span,
+    y: Span,
+}
+
+impl Bounds {
+    fn of(item: &CanvasItem, canvas: &Canvas) -> Option<Self> {
+        let rect = item.bounds(canvas)?;
+        Some(Self {
+            x: Span {
+                start: rect.x() as f64,
+                end: (rect.x() + rect.width()) as f64,
+            },
+            y: Span {
+                start: rect.y() as f64,
+                end: (rect.y() + rect.height()) as f64,
+            },
+        })
+    }
+}
+
+/// A way to line the dragged item up along one axis.
+#[derive(Debug, Clone, Copy)]
+enum Snap {
+    /// Shift by the given distance so an edge or the centre meets `line`.
+    Line { shift: f64, line: f64 },
+    /// Shift so the gaps to the neighbours at `before` and `after` are equal.
+    Gap { shift: f64, before: f64, after: f64 },
+}
+
+impl Snap {
+    fn shift(&self) -> f64 {
+        match self {
+            Snap::Line { shift, .. } | Snap::Gap { shift, .. } => *shift,
+        }
+    }
+}
+
+/// Works out where a dragged `item` proposed at `(x, y)` ends up: lined up
+/// with the other items where it comes close, on the grid otherwise. Alt
+/// turns both off. Also returns the guides to show for that position.
+pub fn snap_drag(
+    canvas: &Canvas,
+    item: &CanvasItem,
+    x: f64,
+    y: f64,
+    state: ModifierType,
+) -> (f64, f64, Vec<Guide>) {
+    if state.contains(ModifierType::ALT_MASK) {
+        return (x, y, Vec::new());
+    }
+    let (grid_x, grid_y) = if snaps(canvas, state) {
+        snap_item(canvas, item, x, y)
+    } else {
+        (x, y)
+    };
+    let (moving, others) = match layout(canvas, item, x, y) {
+        Some(layout) => layout,
+        None => return (grid_x, grid_y, Vec::new()),
+    };
+
+    let along_x: Vec<(Span, Span)> = others.iter().map(|b| (b.x, b.y)).collect();
+    let along_y: Vec<(Span, Span)> = others.iter().map(|b| (b.y, b.x)).collect();
+    let snap_x = best_snap(moving.x, moving.y, &along_x);
+    let snap_y = best_snap(moving.y, moving.x, &along_y);
+
+    let snapped_x = snap_x.map_or(grid_x, |snap| x + snap.shift());
+    let snapped_y = snap_y.map_or(grid_y, |snap| y + snap.shift());
+    let moved = Bounds {
+        x: moving.x.shift(snapped_x - x),
+        y: moving.y.shift(snapped_y - y),
+    };
+
+    let mut guides = Vec::new();
+    match snap_x {
+        Some(Snap::Line { line, .. }) => guides.push(Guide::Vertical(line)),
+        Some(Snap::Gap { before, after, .. }) => guides.push(Guide::HorizontalGaps {
+            y: moved.y.mid(),
+            gaps: [(before, moved.x.start), (moved.x.end, after)],
+        }),
+        None => {}
+    }
+    match snap_y {
+        Some(Snap::Line { line, .. }) => guides.push(Guide::Horizontal(line)),
+        Some(Snap::Gap { before, after, .. }) => guides.push(Guide::VerticalGaps {
+            x: moved.x.mid(),
+            gaps: [(before, moved.y.start), (moved.y.end, after)],
+        }),
+        None => {}
+    }
+    (snapped_x, snapped_y, guides)
+}
+
+/// Bounds `item` would have at `(x, y)`, and those of the items it can line
+/// up with: everything except the items dragged along with it.
+fn layout(canvas: &Canvas, item: &CanvasItem, x: f64, y: f64) -> Option<(Bounds, Vec<Bounds>)> {
+    if item.parent().as_ref() != Some(canvas.upcast_ref()) {
+        return None;
+    }
+    let (old_x, old_y) = canvas.child_position(item);
+    let bounds = Bounds::of(item, canvas)?;
+    let moving = Bounds {
+        x: bounds.x.shift(x - old_x),
+        y: bounds.y.shift(y - old_y),
+    };
+    let others = canvas
+        .items()
+        .iter()
+        .filter(|other| *other != item && !(item.is_selected() && other.is_selected()))
+        .filter_map(|other| Bounds::of(other, canvas))
+        .collect();
+    Some((moving, others))
+}
+
+/// Closest way to line `moving` up with `others` along one axis, each given
+/// as its span on that axis and on the cross axis. `cross` is the moving
+/// item's span on the cross axis.
+fn best_snap(moving: Span, cross: Span, others: &[(Span, Span)]) -> Option<Snap> {
+    let mut best: Option<Snap> = None;
+    let mut consider = |snap: Snap| {
+        if snap.shift().abs() <= SNAP_DISTANCE
+            && best.is_none_or(|best| snap.shift().abs() < best.shift().abs())
+        {
+            best = Some(snap);
+        }
+    };
+
+    for (other, _) in others {
+        for line in [other.start, other.mid(), other.end] {
+            for edge in [moving.start, moving.mid(), moving.end] {
+                consider(Snap::Line {
+                    shift: line - edge,
+                    line,
+                });
+            }
+        }
+    }
+
+    // Equal spacing only counts between neighbours the item sits in line with.
+    let beside: Vec<Span> = others
+        .iter()
+        .filter(|(_, other_cross)| other_cross.overlaps(&cross))
+        .map(|(span, _)| *span)
+        .collect();
+    for before in &beside {
+        for after in &beside {
+            let room = after.start - before.end;
+            let size = moving.end - moving.start;
+            if room <= size {
+                continue;
+            }
+            let start = before.end + (room - size) / 2.;
+            consider(Snap::Gap {
+                shift: start - moving.start,
+                before: before.end,
+                after: after.start,
+            });
+        }
+    }
+    best
+}
+
+/// Shows `guides` over the canvas until they are replaced or cleared.
+pub fn set_guides(canvas: &Canvas, guides: Vec<Guide>) {
+    unsafe {
+        let old = canvas.steal_data::<Vec<Guide>>("guides");
+        if old.is_none() && guides.is_empty() {
+            return;
+        }
+        if !guides.is_empty() {
+            canvas.set_data("guides", guides);
+        }
+    }
+    canvas.queue_draw();
+}
+
+pub fn draw_guides(canvas: &Canvas, snapshot: &Snapshot) {
+    let guides = unsafe {
+        match canvas.data::<Vec<Guide>>("guides") {
+            Some(guides) => guides.as_ref().clone(),
+            None => return,
+        }
+    };
+    let area = canvas.visible_area();
+    let color = RGBA::new(0.9, 0.1, 0.5, 0.9);
+
+    for guide in guides {
+        match guide {
+            Guide::Vertical(x) => {
+                let line = Rect::new(x as f32 - 0.5, area.y(), 1., area.height());
+                snapshot.append_color(&color, &line);
+            }
+            Guide::Horizontal(y) => {
+                let line = Rect::new(area.x(), y as f32 - 0.5, area.width(), 1.);
+                snapshot.append_color(&color, &line);
+            }
+            Guide::HorizontalGaps { y, gaps } => {
+                let y = y as f32;
+                for (start, end) in gaps {
+                    let (start, end) = (start as f32, end as f32);
+                    snapshot.append_color(&color, &Rect::new(start, y - 0.5, end - start, 1.));
+                    for x in [start, end] {
+                        let tick = Rect::new(x - 0.5, y - TICK / 2., 1., TICK);
+                        snapshot.append_color(&color, &tick);
+                    }
+                }
+            }
+            Guide::VerticalGaps { x, gaps } => {
+                let x = x as f32;
+                for (start, end) in gaps {
+                    let (start, end) = (start as f32, end as f32);
+                    snapshot.append_color(&color, &Rect::new(x - 0.5, start, 1., end - start));
+                    for y in [start, end] {
+                        let tick = Rect::new(x - TICK / 2., y - 0.5, TICK, 1.);
+                        snapshot.append_color(&color, &tick);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(start: f64, end: f64) -> Span {
+        Span { start, end }
+    }
+
+    #[test]
+    fn snaps_to_the_closest_line() {
+        let others = [
+            (span(13., 30.), span(0., 10.)),
+            (span(-20., -4.), span(0., 10.)),
+        ];
+        match best_snap(span(0., 10.), span(0., 10.), &others) {
+            Some(Snap::Line { shift, line }) => assert_eq!((shift, line), (3., 13.)),
+            other => panic!("unexpected snap {:?}", other),
+        }
+    }
+
+    #[test]
+    fn lines_up_centres() {
+        let others = [(span(100., 120.), span(50., 60.))];
+        match best_snap(span(104., 114.), span(0., 10.), &others) {
+            Some(Snap::Line { shift, line }) => assert_eq!((shift, line), (1., 110.)),
+            other => panic!("unexpected snap {:?}", other),
+        }
+    }
+
+    #[test]
+    fn ignores_lines_out_of_reach() {
+        let others = [(span(100., 110.), span(0., 10.))];
+        assert!(best_snap(span(0., 10.), span(0., 10.), &others).is_none());
+    }
+
+    #[test]
+    fn centres_between_neighbours_in_line() {
+        let others = [
+            (span(0., 10.), span(0., 10.)),
+            (span(40., 50.), span(0., 10.)),
+        ];
+        match best_snap(span(18., 28.), span(0., 10.), &others) {
+            Some(Snap::Gap {
+                shift,
+                before,
+                after,
+            }) => assert_eq!((shift, before, after), (2., 10., 40.)),
+            other => panic!("unexpected snap {:?}", other),
+        }
+
+        // Neighbours on another row do not count for equal spacing.
+        let others = [
+            (span(0., 10.), span(100., 110.)),
+            (span(40., 50.), span(0., 10.)),
+        ];
+        assert!(best_snap(span(18., 28.), span(0., 10.), &others).is_none());
+    }
+}
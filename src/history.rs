@@ -0,0 +1,340 @@
+//
+// history.rs
+// Copyright (C) 2022 gmg137 <gmg137 AT live.com>
+// Distributed under terms of the GPL-3.0-or-later license.
+//
+use gtk::prelude::*;
+use gtk::Widget;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::canvas::Canvas;
+use crate::canvas_item::CanvasItem;
+use crate::document::ItemData;
+
+pub const HISTORY_LIMIT: usize = 100;
+
+#[derive(Debug, Clone)]
+pub enum Command {
+    Add(ItemData),
+    Remove(ItemData),
+    Change {
+        before: ItemData,
+        after: ItemData,
+    },
+    /// Commands applied together, listed from the topmost item downwards so
+    /// that replaying them in reverse restores the stacking order bottom-up.
+    Group(Vec<Command>),
+}
+
+impl Command {
+    fn undo(&self, canvas: &Canvas) {
+        match self {
+            Command::Add(data) => remove(canvas, data),
+            Command::Remove(data) => add(canvas, data),
+            Command::Change { before, after } => change(canvas, before, after),
+            Command::Group(commands) => commands.iter().rev().for_each(|c| c.undo(canvas)),
+        }
+    }
+
+    fn redo(&self, canvas: &Canvas) {
+        match self {
+            Command::Add(data) => add(canvas, data),
+            Command::Remove(data) => remove(canvas, data),
+            Command::Change { before, after } => change(canvas, after, before),
+            Command::Group(commands) => commands.iter().for_each(|c| c.redo(canvas)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct History {
+    undo: Vec<Command>,
+    redo: Vec<Command>,
+    merge_key: Option<(String, &'static str)>,
+    limit: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            merge_key: None,
+            limit: HISTORY_LIMIT,
+        }
+    }
+}
+
+impl History {
+    /// Sets how many undo steps are kept, dropping the oldest ones.
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        let excess = self.undo.len().saturating_sub(limit);
+        self.undo.drain(..excess);
+    }
+
+    /// Records an already performed command. Consecutive changes to the same
+    /// item sharing `merge` are folded into a single undo step.
+    pub fn push(&mut self, command: Command, merge: Option<&'static str>) {
+        self.redo.clear();
+
+        if let Command::Change { after, .. } = &command {
+            let key = merge.map(|merge| (after.id.clone(), merge));
+            if key.is_some() && key == self.merge_key {
+                if let Some(Command::Change { after: last, .. }) = self.undo.last_mut() {
+                    *last = after.clone();
+                    return;
+                }
+            }
+            self.merge_key = key;
+        } else {
+            self.merge_key = None;
+        }
+
+        self.undo.push(command);
+        if self.undo.len() > self.limit {
+            self.undo.remove(0);
+        }
+    }
+
+    /// Ends the current run of mergeable edits.
+    pub fn seal(&mut self) {
+        self.merge_key = None;
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.merge_key = None;
+    }
+
+    pub fn undo(&mut self, canvas: &Canvas) {
+        self.merge_key = None;
+        if let Some(command) = self.undo.pop() {
+            command.undo(canvas);
+            self.redo.push(command);
+        }
+    }
+
+    pub fn redo(&mut self, canvas: &Canvas) {
+        self.merge_key = None;
+        if let Some(command) = self.redo.pop() {
+            command.redo(canvas);
+            self.undo.push(command);
+        }
+    }
+}
+
+fn add(canvas: &Canvas, data: &ItemData) {
+    let item = data.to_item();
+    canvas.add_item(&item, data.x, data.y);
+    data.apply(canvas, &item);
+}
+
+fn remove(canvas: &Canvas, data: &ItemData) {
+    if let Some(item) = canvas.item_by_id(&data.id) {
+        canvas.remove_item(&item);
+    }
+}
+
+/// Puts the item back to `data`. The stacking order is only touched when
+/// the change moved the item up or down from `current`, so undoing an edit
+/// does not reorder items that were restacked since.
+fn change(canvas: &Canvas, data: &ItemData, current: &ItemData) {
+    if let Some(item) = canvas.item_by_id(&data.id) {
+        data.place(canvas, &item);
+        if data.z != current.z {
+            data.restack(canvas, &item);
+        }
+    }
+}
+
+/// Returns the history of `canvas` if it is a [`Canvas`].
+pub fn history(canvas: &impl IsA<Widget>) -> Option<Rc<RefCell<History>>> {
+    canvas
+        .dynamic_cast_ref::<Canvas>()
+        .map(|canvas| canvas.history())
+}
+
+pub fn record(canvas: &impl IsA<Widget>, command: Command, merge: Option<&'static str>) {
+    if let Some(history) = history(canvas) {
+        history.borrow_mut().push(command, merge);
+    }
+}
+
+/// Records `commands`, listed from the topmost item downwards, as a single
+/// undo step.
+pub fn record_all(canvas: &impl IsA<Widget>, mut commands: Vec<Command>) {
+    match commands.len() {
+        0 => {}
+        1 => record(canvas, commands.pop().unwrap(), None),
+        _ => record(canvas, Command::Group(commands), None),
+    }
+}
+
+/// Removes `items`, given bottom to top, from `canvas` as one undo step.
+pub fn remove_items(canvas: &Canvas, items: &[CanvasItem]) {
+    let mut commands = Vec::new();
+    for item in items.iter().rev() {
+        if let Some(data) = ItemData::capture(item) {
+            canvas.remove_item(item);
+            commands.push(Command::Remove(data));
+        }
+    }
+    record_all(canvas, commands);
+}
+
+/// Records the difference between `before` and the current state of `item`.
+pub fn record_change(item: &CanvasItem, before: Option<ItemData>, merge: Option<&'static str>) {
+    if let Some(canvas) = item.parent() {
+        record_changes(&canvas, std::slice::from_ref(item), vec![before], merge);
+    }
+}
+
+/// Records the changes of several items as a single undo step. `items` must
+/// be ordered from the topmost item downwards.
+pub fn record_changes(
+    canvas: &impl IsA<Widget>,
+    items: &[CanvasItem],
+    before: Vec<Option<ItemData>>,
+    merge: Option<&'static str>,
+) {
+    let mut commands: Vec<Command> = items
+        .iter()
+        .zip(before)
+        .filter_map(|(item, before)| {
+            let after = ItemData::capture(item)?;
+            let before = before?;
+            (before != after).then_some(Command::Change { before, after })
+        })
+        .collect();
+
+    match commands.len() {
+        0 => {}
+        1 => record(canvas, commands.pop().unwrap(), merge),
+        _ => record(canvas, Command::Group(commands), merge),
+    }
+}
+
+pub fn seal(canvas: &impl IsA<Widget>) {
+    if let Some(history) = history(canvas) {
+        history.borrow_mut().seal();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(id: &str, x: f64) -> ItemData {
+        ItemData {
+            id: id.to_string(),
+            x,
+            y: 0.,
+            angle: 0.,
+            scale_x: 1.,
+            scale_y: 1.,
+            label: "Item".to_string(),
+            color: None,
+            css_class: None,
+            image: None,
+            locked: false,
+            opacity: 1.,
+            z: 0,
+        }
+    }
+
+    fn change(id: &str, from: f64, to: f64) -> Command {
+        Command::Change {
+            before: data(id, from),
+            after: data(id, to),
+        }
+    }
+
+    fn last_after(history: &History) -> f64 {
+        match history.undo.last() {
+            Some(Command::Change { after, .. }) => after.x,
+            other => panic!("unexpected command {:?}", other),
+        }
+    }
+
+    #[test]
+    fn merges_changes_sharing_item_and_key() {
+        let mut history = History::default();
+        history.push(change("a", 0., 1.), Some("x"));
+        history.push(change("a", 1., 2.), Some("x"));
+        assert_eq!(history.undo.len(), 1);
+        assert_eq!(last_after(&history), 2.);
+        match &history.undo[0] {
+            Command::Change { before, .. } => assert_eq!(before.x, 0.),
+            other => panic!("unexpected command {:?}", other),
+        }
+    }
+
+    #[test]
+    fn keeps_changes_apart_otherwise() {
+        let mut history = History::default();
+        history.push(change("a", 0., 1.), Some("x"));
+        history.push(change("b", 0., 1.), Some("x"));
+        history.push(change("b", 1., 2.), Some("y"));
+        history.push(change("b", 2., 3.), None);
+        history.push(change("b", 3., 4.), None);
+        assert_eq!(history.undo.len(), 5);
+    }
+
+    #[test]
+    fn seal_ends_a_merge() {
+        let mut history = History::default();
+        history.push(change("a", 0., 1.), Some("x"));
+        history.seal();
+        history.push(change("a", 1., 2.), Some("x"));
+        assert_eq!(history.undo.len(), 2);
+    }
+
+    #[test]
+    fn other_commands_end_a_merge() {
+        let mut history = History::default();
+        history.push(change("a", 0., 1.), Some("x"));
+        history.push(Command::Add(data("b", 0.)), None);
+        history.push(change("a", 1., 2.), Some("x"));
+        assert_eq!(history.undo.len(), 3);
+    }
+
+    #[test]
+    fn push_clears_redo() {
+        let mut history = History::default();
+        history.redo.push(change("a", 0., 1.));
+        history.push(change("a", 1., 2.), None);
+        assert!(history.redo.is_empty());
+    }
+
+    #[test]
+    fn limit_drops_the_oldest_steps() {
+        let mut history = History::default();
+        for i in 0..HISTORY_LIMIT + 2 {
+            history.push(change("a", i as f64, i as f64 + 1.), None);
+        }
+        assert_eq!(history.undo.len(), HISTORY_LIMIT);
+        assert_eq!(last_after(&history), (HISTORY_LIMIT + 2) as f64);
+        match &history.undo[0] {
+            Command::Change { after, .. } => assert_eq!(after.x, 3.),
+            other => panic!("unexpected command {:?}", other),
+        }
+    }
+
+    #[test]
+    fn set_limit_trims_the_oldest_steps() {
+        let mut history = History::default();
+        for i in 0..5 {
+            history.push(change("a", i as f64, i as f64 + 1.), None);
+        }
+        history.set_limit(2);
+        assert_eq!(history.undo.len(), 2);
+        assert_eq!(last_after(&history), 5.);
+
+        history.push(change("a", 5., 6.), None);
+        assert_eq!(history.undo.len(), 2);
+        assert_eq!(last_after(&history), 6.);
+    }
+}
@@ -0,0 +1,29 @@
+//
+// ids.rs
+// Copyright (C) 2022 gmg137 <gmg137 AT live.com>
+// Distributed under terms of the GPL-3.0-or-later license.
+//
+use gtk::glib;
+use std::cell::Cell;
+
+/// Hands out item ids and default labels for one canvas.
+///
+/// Ids are random UUIDs, so items keep them when they are saved, loaded or
+/// moved to a canvas in another window or process without clashing with the
+/// items already there. Labels are only a convenience for the user and are
+/// numbered separately.
+#[derive(Debug, Default)]
+pub struct IdAllocator {
+    labels: Cell<u32>,
+}
+
+impl IdAllocator {
+    pub fn next_id(&self) -> String {
+        glib::uuid_string_random().to_string()
+    }
+
+    pub fn next_label(&self) -> String {
+        self.labels.set(self.labels.get() + 1);
+        format!("Item {}", self.labels.get())
+    }
+}
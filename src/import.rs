@@ -0,0 +1,277 @@
+//
+// import.rs
+// Copyright (C) 2022 gmg137 <gmg137 AT live.com>
+// Distributed under terms of the GPL-3.0-or-later license.
+//
+use gtk::gdk::Texture;
+use gtk::gdk_pixbuf::Pixbuf;
+use gtk::glib::clone;
+use gtk::prelude::*;
+use gtk::{gio, glib};
+use std::io::{Error, ErrorKind, Result};
+
+use crate::canvas::Canvas;
+use crate::canvas_item::CanvasItem;
+use crate::document::{Document, ItemData};
+use crate::history::{record_all, Command};
+
+/// Distance between the items created for several dropped files.
+const CASCADE: f64 = 20.;
+
+/// Adds the files dropped from a file manager at `(x, y)`: images become
+/// image items, saved canvases are merged in and any other text file,
+/// including JSON that is not a canvas, becomes an item labelled with its
+/// first line. The files are read in the background and the items appear
+/// once all of them are loaded; files that cannot be read are skipped. The
+/// whole drop is a single undo step. Returns whether any of the files has a
+/// type that can be imported.
+pub fn import_files(canvas: &Canvas, files: &[gio::File], x: f64, y: f64) -> bool {
+    let files: Vec<(gio::File, FileKind)> = files
+        .iter()
+        .filter_map(|file| match file_kind(file) {
+            Ok(kind) => Some((file.clone(), kind)),
+            Err(err) => {
+                glib::g_warning!("gtk4-dnd", "Cannot import {}: {}", file.uri(), err);
+                None
+            }
+        })
+        .collect();
+    if files.is_empty() {
+        return false;
+    }
+
+    glib::MainContext::default().spawn_local(clone!(@weak canvas => async move {
+        let mut items = Vec::new();
+        for (i, (file, kind)) in files.iter().enumerate() {
+            let offset = i as f64 * CASCADE;
+            match load_file(file, kind).await {
+                Ok(contents) => items.append(&mut add_contents(&canvas, contents, x + offset, y + offset)),
+                Err(err) => glib::g_warning!("gtk4-dnd", "Cannot import {}: {}", file.uri(), err),
+            }
+        }
+        record_added(&canvas, &items);
+    }));
+    true
+}
+
+/// Adds an image item showing `texture` at `(x, y)`.
+pub fn import_texture(canvas: &Canvas, texture: &Texture, x: f64, y: f64) -> bool {
+    let item = put_item(canvas, x, y);
+    item.set_label("Image");
+    item.set_texture(Some(texture));
+    record_added(canvas, &[item]);
+    true
+}
+
+/// Whether `json` has the shape of a saved canvas, whatever its version.
+fn is_document(json: &[u8]) -> bool {
+    serde_json::from_slice::<serde_json::Value>(json)
+        .is_ok_and(|value| value.get("version").is_some() && value.get("items").is_some())
+}
+
+/// How a dropped file is imported, going by its name.
+enum FileKind {
+    Image { name: String },
+    Text { name: String, is_json: bool },
+}
+
+/// What a dropped file turned out to hold once it was read.
+#[derive(Debug)]
+enum Contents {
+    Image { name: String, texture: Texture },
+    Document(Document),
+    Text(String),
+}
+
+fn file_kind(file: &gio::File) -> Result<FileKind> {
+    let name = file
+        .basename()
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "file has no name"))?;
+    let (content_type, _) = gio::content_type_guess(Some(&name), &[]);
+    let name = name.to_string_lossy().into_owned();
+
+    if gio::content_type_is_a(&content_type, "image/*") {
+        return Ok(FileKind::Image { name });
+    }
+    let is_json = gio::content_type_is_a(&content_type, "application/json");
+    if is_json || gio::content_type_is_a(&content_type, "text/plain") {
+        return Ok(FileKind::Text { name, is_json });
+    }
+    Err(Error::new(
+        ErrorKind::InvalidData,
+        format!("unsupported file type {}", content_type),
+    ))
+}
+
+/// Reads and decodes `file` without blocking the main loop.
+async fn load_file(file: &gio::File, kind: &FileKind) -> Result<Contents> {
+    match kind {
+        FileKind::Image { name } => {
+            let stream = file
+                .read_future(glib::PRIORITY_DEFAULT)
+                .await
+                .map_err(glib_error)?;
+            let pixbuf = Pixbuf::from_stream_future(&stream)
+                .await
+                .map_err(glib_error)?;
+            Ok(Contents::Image {
+                name: name.clone(),
+                texture: Texture::for_pixbuf(&pixbuf),
+            })
+        }
+        FileKind::Text { name, is_json } => {
+            let (bytes, _) = file.load_contents_future().await.map_err(glib_error)?;
+            parse_text(&bytes, *is_json, name)
+        }
+    }
+}
+
+/// Reads the contents of a text file: a saved canvas if it is JSON in the
+/// canvas format, otherwise its first non-empty line, or `name` if it has
+/// none.
+fn parse_text(bytes: &[u8], is_json: bool, name: &str) -> Result<Contents> {
+    if is_json {
+        match Document::from_json(bytes) {
+            Ok(document) => return Ok(Contents::Document(document)),
+            // A canvas from a newer release should not turn into text.
+            Err(err) if is_document(bytes) => return Err(err),
+            Err(_) => {}
+        }
+    }
+    let text = String::from_utf8_lossy(bytes);
+    let line = text.lines().map(str::trim).find(|line| !line.is_empty());
+    Ok(Contents::Text(line.unwrap_or(name).to_string()))
+}
+
+fn add_contents(canvas: &Canvas, contents: Contents, x: f64, y: f64) -> Vec<CanvasItem> {
+    match contents {
+        Contents::Image { name, texture } => {
+            let item = put_item(canvas, x, y);
+            item.set_label(&name);
+            item.set_texture(Some(&texture));
+            vec![item]
+        }
+        Contents::Document(document) => merge_document(canvas, &document, x, y),
+        Contents::Text(label) => {
+            let item = put_item(canvas, x, y);
+            item.set_label(&label);
+            vec![item]
+        }
+    }
+}
+
+/// Adds the items of a dropped or pasted document with their top-left
+/// corner at `(x, y)`, as one undo step.
+pub fn import_document(canvas: &Canvas, document: &Document, x: f64, y: f64) -> Vec<CanvasItem> {
+    let items = merge_document(canvas, document, x, y);
+    record_added(canvas, &items);
+    items
+}
+
+/// Adds one item per non-empty line of `text`, stacked downwards from
+/// `(x, y)`, as one undo step.
+pub fn import_text(canvas: &Canvas, text: &str, x: f64, y: f64) -> Vec<CanvasItem> {
+    let items: Vec<CanvasItem> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .enumerate()
+        .map(|(i, line)| {
+            let item = put_item(canvas, x, y + i as f64 * 2. * CASCADE);
+            item.set_label(line);
+            item
+        })
+        .collect();
+    record_added(canvas, &items);
+    items
+}
+
+/// Puts the items of `document` on top of the canvas with their top-left
+/// corner at `(x, y)`. The items keep their ids unless they clash with the
+/// items already on the canvas.
+fn merge_document(canvas: &Canvas, document: &Document, x: f64, y: f64) -> Vec<CanvasItem> {
+    let mut items = document.items.clone();
+    items.sort_by_key(|data| data.z);
+    let (left, top) = document.origin();
+
+    items
+        .iter()
+        .map(|data| {
+            let item = data.to_item();
+            canvas.add_item(&item, x + data.x - left, y + data.y - top);
+            item
+        })
+        .collect()
+}
+
+fn put_item(canvas: &Canvas, x: f64, y: f64) -> CanvasItem {
+    let item = CanvasItem::new();
+    canvas.add_item(&item, x, y);
+    item
+}
+
+/// Records `items`, given bottom to top, as one undo step.
+fn record_added(canvas: &Canvas, items: &[CanvasItem]) {
+    let commands = items
+        .iter()
+        .rev()
+        .filter_map(ItemData::capture)
+        .map(Command::Add)
+        .collect();
+    record_all(canvas, commands);
+}
+
+fn glib_error(err: glib::Error) -> Error {
+    Error::other(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::FORMAT_VERSION;
+
+    #[test]
+    fn recognizes_documents() {
+        assert!(is_document(br#"{"version": 1, "items": []}"#));
+        assert!(is_document(br#"{"version": 99, "items": [{"x": 1}]}"#));
+        assert!(!is_document(br#"{"items": []}"#));
+        assert!(!is_document(br#"{"version": 1}"#));
+        assert!(!is_document(b"[1, 2]"));
+        assert!(!is_document(b"not json"));
+    }
+
+    #[test]
+    fn canvas_json_becomes_a_document() {
+        let json = format!(r#"{{"version": {}, "items": []}}"#, FORMAT_VERSION);
+        assert!(matches!(
+            parse_text(json.as_bytes(), true, "a.json"),
+            Ok(Contents::Document(_))
+        ));
+    }
+
+    #[test]
+    fn other_json_becomes_text() {
+        let json = b"\n  {\"name\": \"x\"}\n";
+        match parse_text(json, true, "a.json") {
+            Ok(Contents::Text(label)) => assert_eq!(label, r#"{"name": "x"}"#),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn newer_documents_are_refused() {
+        assert!(parse_text(br#"{"version": 999, "items": []}"#, true, "a.json").is_err());
+    }
+
+    #[test]
+    fn text_uses_its_first_line_or_the_name() {
+        match parse_text(b"\n first \nsecond", false, "a.txt") {
+            Ok(Contents::Text(label)) => assert_eq!(label, "first"),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse_text(b"  \n", false, "a.txt") {
+            Ok(Contents::Text(label)) => assert_eq!(label, "a.txt"),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,332 @@
+//
+// inspector.rs
+// Copyright (C) 2022 gmg137 <gmg137 AT live.com>
+// Distributed under terms of the GPL-3.0-or-later license.
+//
+use gtk::gdk::RGBA;
+use gtk::glib;
+use gtk::glib::clone;
+use gtk::glib::{Binding, BindingFlags, SignalHandlerId};
+use gtk::prelude::*;
+use gtk::subclass::prelude::*;
+use gtk::{
+    Align, ColorButton, DropDown, Entry, Grid, Label, Orientation, Scale, SpinButton, StringList,
+};
+use std::cell::{Cell, RefCell};
+
+use crate::canvas::Canvas;
+use crate::canvas_item::CanvasItem;
+use crate::document::ItemData;
+use crate::history;
+use crate::palette::DEFAULT_STYLES;
+
+/// Largest coordinate that can be typed into the X and Y fields.
+const MAX_POSITION: f64 = 100_000.;
+
+glib::wrapper! {
+    pub struct Inspector(ObjectSubclass<ExInspector>)
+        @extends gtk::Box, gtk::Widget,
+        @implements gtk::Orientable;
+}
+
+impl Inspector {
+    /// Creates a panel that shows and edits the item selected on `canvas`.
+    /// It is empty while no item or several items are selected.
+    pub fn new(canvas: &Canvas) -> Self {
+        let inspector: Self =
+            glib::Object::new(&[("orientation", &Orientation::Vertical), ("spacing", &12)])
+                .expect("Failed to create Inspector");
+
+        canvas.connect_local(
+            "selection-changed",
+            false,
+            clone!(@weak inspector => @default-return None, move |values| {
+                let canvas = values[0].get::<Canvas>().unwrap();
+                let selected = canvas.selected_items();
+                history::seal(&canvas);
+                match selected.as_slice() {
+                    [item] => inspector.set_item(Some(item)),
+                    _ => inspector.set_item(None),
+                }
+                None
+            }),
+        );
+        canvas.connect_local(
+            "item-activated",
+            false,
+            clone!(@weak inspector => @default-return None, move |_| {
+                let imp = inspector.imp();
+                let label = imp.label.borrow();
+                label.as_ref().unwrap().grab_focus();
+                None
+            }),
+        );
+        inspector
+    }
+
+    /// Replaces the style classes offered in the Style dropdown.
+    pub fn set_styles(&self, styles: &[&str]) {
+        let imp = self.imp();
+        *imp.styles.borrow_mut() = styles.iter().map(|s| s.to_string()).collect();
+        let mut names = vec!["None"];
+        names.extend_from_slice(styles);
+        imp.syncing.set(true);
+        let style = imp.style.borrow();
+        style
+            .as_ref()
+            .unwrap()
+            .set_model(Some(&StringList::new(&names)));
+        imp.syncing.set(false);
+        self.sync_style();
+    }
+
+    pub fn item(&self) -> Option<CanvasItem> {
+        self.imp().item.borrow().clone()
+    }
+
+    /// Shows `item` in the panel, keeping the fields in sync with it.
+    pub fn set_item(&self, item: Option<&CanvasItem>) {
+        let imp = self.imp();
+        if let Some(old) = imp.item.replace(None) {
+            if let Some(handler) = imp.style_handler.take() {
+                old.disconnect(handler);
+            }
+        }
+        for binding in imp.bindings.borrow_mut().drain(..) {
+            binding.unbind();
+        }
+
+        let grid = imp.grid.borrow();
+        let grid = grid.as_ref().unwrap();
+        grid.set_sensitive(item.is_some());
+        let item = match item {
+            Some(item) => item,
+            None => {
+                imp.label.borrow().as_ref().unwrap().set_text("");
+                return;
+            }
+        };
+
+        let flags = BindingFlags::SYNC_CREATE;
+        let mut bindings = imp.bindings.borrow_mut();
+        bindings.push(
+            item.bind_property("label", imp.label.borrow().as_ref().unwrap(), "text")
+                .flags(flags)
+                .build(),
+        );
+        bindings.push(
+            item.bind_property("x", imp.x.borrow().as_ref().unwrap(), "value")
+                .flags(flags)
+                .build(),
+        );
+        bindings.push(
+            item.bind_property("y", imp.y.borrow().as_ref().unwrap(), "value")
+                .flags(flags)
+                .build(),
+        );
+        bindings.push(
+            item.bind_property("angle", imp.angle.borrow().as_ref().unwrap(), "value")
+                .flags(flags)
+                .transform_to(|_, value| {
+                    let angle = value.get::<f32>().unwrap();
+                    Some((angle.rem_euclid(360.) as f64).to_value())
+                })
+                .build(),
+        );
+        bindings.push(
+            item.bind_property("color", imp.color.borrow().as_ref().unwrap(), "rgba")
+                .flags(flags)
+                .transform_to(|_, value| {
+                    let color = value.get::<Option<RGBA>>().unwrap()?;
+                    Some(color.to_value())
+                })
+                .build(),
+        );
+        let opacity = imp.opacity.borrow();
+        bindings.push(
+            item.bind_property("opacity", &opacity.as_ref().unwrap().adjustment(), "value")
+                .flags(flags)
+                .build(),
+        );
+        drop(bindings);
+
+        // The style is synced by hand so picking a style can be told apart
+        // from the dropdown following the item.
+        let handler = item.connect_notify_local(
+            Some("css-class"),
+            clone!(@weak self as inspector => move |_, _| inspector.sync_style()),
+        );
+        imp.style_handler.replace(Some(handler));
+        imp.item.replace(Some(item.clone()));
+        self.sync_style();
+    }
+
+    /// Shows the style class of the item in the Style dropdown, or None for
+    /// a class that is not offered.
+    fn sync_style(&self) {
+        let imp = self.imp();
+        let class = match self.item() {
+            Some(item) => item.css_class(),
+            None => return,
+        };
+        let position = class
+            .and_then(|class| imp.styles.borrow().iter().position(|style| *style == class))
+            .map_or(0, |i| i as u32 + 1);
+        imp.syncing.set(true);
+        imp.style.borrow().as_ref().unwrap().set_selected(position);
+        imp.syncing.set(false);
+    }
+
+    /// Applies an edit made in one of the fields to the shown item and
+    /// records it, merging repeated edits of the same field.
+    fn edit(&self, merge: Option<&'static str>, f: impl FnOnce(&CanvasItem)) {
+        let item = match self.item() {
+            Some(item) => item,
+            None => return,
+        };
+        let before = ItemData::capture(&item);
+        f(&item);
+        history::record_change(&item, before, merge);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ExInspector {
+    item: RefCell<Option<CanvasItem>>,
+    bindings: RefCell<Vec<Binding>>,
+    style_handler: RefCell<Option<SignalHandlerId>>,
+    /// Set while the Style dropdown is updated to match the item.
+    syncing: Cell<bool>,
+    styles: RefCell<Vec<String>>,
+
+    grid: RefCell<Option<Grid>>,
+    label: RefCell<Option<Entry>>,
+    x: RefCell<Option<SpinButton>>,
+    y: RefCell<Option<SpinButton>>,
+    angle: RefCell<Option<SpinButton>>,
+    color: RefCell<Option<ColorButton>>,
+    style: RefCell<Option<DropDown>>,
+    opacity: RefCell<Option<Scale>>,
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for ExInspector {
+    const NAME: &'static str = "Inspector";
+    type Type = Inspector;
+    type ParentType = gtk::Box;
+
+    fn class_init(klass: &mut Self::Class) {
+        klass.set_css_name("inspector");
+    }
+}
+
+impl ObjectImpl for ExInspector {
+    fn constructed(&self, obj: &Self::Type) {
+        self.parent_constructed(obj);
+        obj.set_margin_top(12);
+        obj.set_margin_bottom(12);
+        obj.set_margin_start(12);
+        obj.set_margin_end(12);
+
+        let grid = Grid::new();
+        grid.set_row_spacing(6);
+        grid.set_column_spacing(12);
+        obj.append(&grid);
+
+        let label = Entry::new();
+        label.set_width_chars(12);
+        label.connect_changed(clone!(@weak obj => move |entry| {
+            obj.edit(Some("label"), |item| {
+                if item.label() != entry.text() {
+                    item.set_label(&entry.text());
+                }
+            });
+        }));
+        label.connect_activate(clone!(@weak obj => move |_| {
+            if let Some(canvas) = obj.item().and_then(|item| item.parent()) {
+                history::seal(&canvas);
+            }
+        }));
+        attach_row(&grid, 0, "Label", &label);
+
+        let x = SpinButton::with_range(-MAX_POSITION, MAX_POSITION, 1.);
+        x.connect_value_changed(clone!(@weak obj => move |spin| {
+            obj.edit(Some("x"), |item| item.set_position(spin.value(), item.position().1));
+        }));
+        attach_row(&grid, 1, "X", &x);
+
+        let y = SpinButton::with_range(-MAX_POSITION, MAX_POSITION, 1.);
+        y.connect_value_changed(clone!(@weak obj => move |spin| {
+            obj.edit(Some("y"), |item| item.set_position(item.position().0, spin.value()));
+        }));
+        attach_row(&grid, 2, "Y", &y);
+
+        let angle = SpinButton::with_range(0., 360., 1.);
+        angle.set_wrap(true);
+        angle.connect_value_changed(clone!(@weak obj => move |spin| {
+            obj.edit(Some("angle"), |item| {
+                let difference = (item.angle() as f64 - spin.value()).rem_euclid(360.);
+                if difference.min(360. - difference) >= 0.5 {
+                    item.set_angle(spin.value() as f32);
+                }
+            });
+        }));
+        attach_row(&grid, 3, "Angle", &angle);
+
+        let color = ColorButton::new();
+        color.set_halign(Align::Start);
+        color.connect_color_set(clone!(@weak obj => move |button| {
+            obj.edit(None, |item| item.set_color(button.rgba()));
+        }));
+        attach_row(&grid, 4, "Color", &color);
+
+        let style = DropDown::from_strings(&[]);
+        style.connect_selected_notify(clone!(@weak obj => move |dropdown| {
+            let imp = obj.imp();
+            if imp.syncing.get() {
+                return;
+            }
+            let class = match dropdown.selected() {
+                0 => None,
+                i => imp.styles.borrow().get(i as usize - 1).cloned(),
+            };
+            let color = imp.color.borrow().as_ref().unwrap().rgba();
+            obj.edit(None, |item| match class {
+                Some(class) if item.css_class().as_ref() != Some(&class) => {
+                    item.set_css_class(&class)
+                }
+                None if item.css_class().is_some() => item.set_color(color),
+                _ => {}
+            });
+        }));
+        attach_row(&grid, 5, "Style", &style);
+
+        let opacity = Scale::with_range(Orientation::Horizontal, 0., 1., 0.05);
+        opacity.set_hexpand(true);
+        opacity.connect_value_changed(clone!(@weak obj => move |scale| {
+            obj.edit(Some("opacity"), |item| item.set_opacity(scale.value()));
+        }));
+        attach_row(&grid, 6, "Opacity", &opacity);
+
+        *self.grid.borrow_mut() = Some(grid);
+        *self.label.borrow_mut() = Some(label);
+        *self.x.borrow_mut() = Some(x);
+        *self.y.borrow_mut() = Some(y);
+        *self.angle.borrow_mut() = Some(angle);
+        *self.color.borrow_mut() = Some(color);
+        *self.style.borrow_mut() = Some(style);
+        *self.opacity.borrow_mut() = Some(opacity);
+
+        obj.set_styles(&DEFAULT_STYLES);
+    }
+}
+
+impl WidgetImpl for ExInspector {}
+impl BoxImpl for ExInspector {}
+
+fn attach_row(grid: &Grid, row: i32, title: &str, widget: &impl IsA<gtk::Widget>) {
+    let label = Label::new(Some(title));
+    label.set_xalign(0.);
+    grid.attach(&label, 0, row, 1, 1);
+    grid.attach(widget, 1, row, 1, 1);
+}
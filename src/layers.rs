@@ -0,0 +1,241 @@
+//
+// layers.rs
+// Copyright (C) 2022 gmg137 <gmg137 AT live.com>
+// Distributed under terms of the GPL-3.0-or-later license.
+//
+use gtk::gdk::{ContentProvider, DragAction};
+use gtk::glib::clone;
+use gtk::glib::{Binding, BindingFlags};
+use gtk::prelude::*;
+use gtk::subclass::prelude::*;
+use gtk::{gio, glib};
+use gtk::{
+    DragSource, DropTarget, Image, Label, ListItem, ListView, Orientation, ScrolledWindow,
+    SignalListItemFactory, SingleSelection, WidgetPaintable, INVALID_LIST_POSITION,
+};
+use std::cell::{Cell, RefCell};
+
+use crate::arrange::set_stacking_order;
+use crate::canvas::Canvas;
+use crate::canvas_item::CanvasItem;
+
+glib::wrapper! {
+    pub struct Layers(ObjectSubclass<ExLayers>)
+        @extends gtk::Box, gtk::Widget,
+        @implements gtk::Orientable;
+}
+
+impl Layers {
+    /// Creates a list of the items on `canvas`, topmost first. Selecting a
+    /// row selects its item and dragging a row changes the stacking order.
+    pub fn new(canvas: &Canvas) -> Self {
+        let layers: Self = glib::Object::new(&[("orientation", &Orientation::Vertical)])
+            .expect("Failed to create Layers");
+
+        let children = canvas.observe_children();
+        children.connect_items_changed(clone!(@weak layers, @weak canvas => move |_, _, _, _| {
+            layers.refresh(&canvas);
+        }));
+        canvas.connect_local(
+            "selection-changed",
+            false,
+            clone!(@weak layers => @default-return None, move |values| {
+                let canvas = values[0].get::<Canvas>().unwrap();
+                layers.sync_selection(&canvas);
+                None
+            }),
+        );
+        let imp = layers.imp();
+        imp.selection
+            .borrow()
+            .as_ref()
+            .unwrap()
+            .connect_selected_notify(clone!(@weak layers, @weak canvas => move |selection| {
+                if layers.imp().syncing.get() {
+                    return;
+                }
+                if let Some(item) = selection.selected_item() {
+                    let item: CanvasItem = item.downcast().unwrap();
+                    canvas.select_only(Some(&item));
+                }
+            }));
+        *imp.children.borrow_mut() = Some(children);
+
+        layers.refresh(canvas);
+        layers
+    }
+
+    /// Rebuilds the rows if the items or their order changed.
+    fn refresh(&self, canvas: &Canvas) {
+        let imp = self.imp();
+        let store = imp.store.borrow();
+        let store = store.as_ref().unwrap();
+        let items: Vec<glib::Object> = canvas
+            .items()
+            .into_iter()
+            .rev()
+            .map(|i| i.upcast())
+            .collect();
+        let rows: Vec<glib::Object> = (0..store.n_items()).filter_map(|i| store.item(i)).collect();
+        if rows != items {
+            imp.syncing.set(true);
+            store.splice(0, store.n_items(), &items);
+            imp.syncing.set(false);
+            self.sync_selection(canvas);
+        }
+    }
+
+    /// Highlights the row of the selected item when exactly one is selected.
+    fn sync_selection(&self, canvas: &Canvas) {
+        let imp = self.imp();
+        let selection = imp.selection.borrow();
+        let selection = selection.as_ref().unwrap();
+        let position = match canvas.selected_items().as_slice() {
+            [item] => {
+                let store = imp.store.borrow();
+                let store = store.as_ref().unwrap();
+                (0..store.n_items())
+                    .find(|&i| store.item(i).as_ref() == Some(item.upcast_ref()))
+                    .unwrap_or(INVALID_LIST_POSITION)
+            }
+            _ => INVALID_LIST_POSITION,
+        };
+        imp.syncing.set(true);
+        selection.set_selected(position);
+        imp.syncing.set(false);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ExLayers {
+    store: RefCell<Option<gio::ListStore>>,
+    selection: RefCell<Option<SingleSelection>>,
+    children: RefCell<Option<gio::ListModel>>,
+    syncing: Cell<bool>,
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for ExLayers {
+    const NAME: &'static str = "Layers";
+    type Type = Layers;
+    type ParentType = gtk::Box;
+
+    fn class_init(klass: &mut Self::Class) {
+        klass.set_css_name("layers");
+    }
+}
+
+impl ObjectImpl for ExLayers {
+    fn constructed(&self, obj: &Self::Type) {
+        self.parent_constructed(obj);
+
+        let store = gio::ListStore::new(CanvasItem::static_type());
+        let selection = SingleSelection::new(Some(&store));
+        selection.set_autoselect(false);
+        selection.set_can_unselect(true);
+
+        let factory = SignalListItemFactory::new();
+        factory.connect_setup(|_, list_item| setup_row(list_item));
+        factory.connect_bind(|_, list_item| bind_row(list_item));
+        factory.connect_unbind(|_, list_item| unsafe {
+            if let Some(bindings) = list_item.steal_data::<Vec<Binding>>("bindings") {
+                bindings.iter().for_each(Binding::unbind);
+            }
+        });
+
+        let view = ListView::new(Some(&selection), Some(&factory));
+        let scrolled = ScrolledWindow::new();
+        scrolled.set_child(Some(&view));
+        scrolled.set_vexpand(true);
+        scrolled.set_min_content_width(160);
+        obj.append(&scrolled);
+
+        *self.store.borrow_mut() = Some(store);
+        *self.selection.borrow_mut() = Some(selection);
+    }
+}
+
+impl WidgetImpl for ExLayers {}
+impl BoxImpl for ExLayers {}
+
+fn setup_row(list_item: &ListItem) {
+    let row = gtk::Box::new(Orientation::Horizontal, 6);
+    row.set_margin_top(3);
+    row.set_margin_bottom(3);
+    row.set_margin_start(6);
+    row.set_margin_end(6);
+    let label = Label::new(None);
+    label.set_xalign(0.);
+    label.set_hexpand(true);
+    row.append(&label);
+    let lock = Image::from_icon_name("changes-prevent-symbolic");
+    row.append(&lock);
+    list_item.set_child(Some(&row));
+
+    let source = DragSource::new();
+    source.set_actions(DragAction::MOVE);
+    source.connect_prepare(
+        clone!(@weak list_item => @default-return None, move |_, _, _| {
+            let item = list_item.item()?;
+            Some(ContentProvider::for_value(&item.to_value()))
+        }),
+    );
+    source.connect_drag_begin(clone!(@weak row => move |source, _| {
+        source.set_icon(Some(&WidgetPaintable::new(Some(&row))), 0, 0);
+    }));
+    row.add_controller(&source);
+
+    // Dropping on the upper half of a row puts the dragged item above the
+    // row's item, the lower half below it.
+    let target = DropTarget::new(CanvasItem::static_type(), DragAction::MOVE);
+    target.connect_drop(
+        clone!(@weak list_item, @weak row => @default-return false, move |_, value, _, y| {
+            let dragged = match value.get::<CanvasItem>() {
+                Ok(item) => item,
+                Err(_) => return false,
+            };
+            let item: CanvasItem = match list_item.item() {
+                Some(item) => item.downcast().unwrap(),
+                None => return false,
+            };
+            let canvas = match dragged.parent().and_then(|p| p.downcast::<Canvas>().ok()) {
+                Some(canvas) => canvas,
+                None => return false,
+            };
+            if dragged == item || item.parent().as_ref() != Some(canvas.upcast_ref()) {
+                return false;
+            }
+
+            let mut order = canvas.items();
+            order.retain(|other| *other != dragged);
+            let index = order.iter().position(|other| *other == item).unwrap();
+            let above = y < row.height() as f64 / 2.;
+            order.insert(if above { index + 1 } else { index }, dragged.clone());
+            set_stacking_order(&canvas, &order, &[dragged]);
+            true
+        }),
+    );
+    row.add_controller(&target);
+}
+
+fn bind_row(list_item: &ListItem) {
+    let item = match list_item.item() {
+        Some(item) => item,
+        None => return,
+    };
+    let row = list_item.child().unwrap();
+    let label = row.first_child().unwrap();
+    let lock = label.next_sibling().unwrap();
+    let flags = BindingFlags::SYNC_CREATE;
+    let bindings = vec![
+        item.bind_property("label", &label, "label")
+            .flags(flags)
+            .build(),
+        item.bind_property("locked", &lock, "visible")
+            .flags(flags)
+            .build(),
+    ];
+    unsafe {
+        list_item.set_data("bindings", bindings);
+    }
+}
@@ -0,0 +1,59 @@
+//
+// lib.rs
+// Copyright (C) 2022 gmg137 <gmg137 AT live.com>
+// Distributed under terms of the GPL-3.0-or-later license.
+//
+//! Drag-and-drop canvas widgets for GTK 4.
+//!
+//! Call [`init`] once after GTK is initialized, then put a [`Canvas`], or a
+//! [`CanvasView`] showing one inside a `gtk::ScrolledWindow`, and a
+//! palette built with [`PaletteBuilder`] into a window. An [`Inspector`]
+//! next to the canvas edits the selected item and [`Layers`] lists the
+//! items in stacking order.
+use gtk::{gdk, CssProvider, StyleContext};
+
+pub mod arrange;
+pub mod canvas;
+pub mod canvas_item;
+mod clipboard;
+pub mod color_swatch;
+pub mod css_button;
+pub mod document;
+pub mod export;
+pub mod formats;
+mod grid;
+mod guides;
+pub mod history;
+mod ids;
+mod import;
+pub mod inspector;
+pub mod layers;
+pub mod palette;
+mod selection;
+pub mod view;
+
+pub use arrange::{Alignment, Stacking};
+pub use canvas::{Canvas, CanvasBuilder};
+pub use canvas_item::CanvasItem;
+pub use color_swatch::ColorSwatch;
+pub use css_button::CssButton;
+pub use document::Document;
+pub use inspector::Inspector;
+pub use layers::Layers;
+pub use palette::PaletteBuilder;
+pub use view::CanvasView;
+
+/// Registers the drag-and-drop formats and installs the stylesheet the
+/// widgets rely on.
+pub fn init() {
+    formats::register_formats();
+    load_css();
+}
+
+/// Adds the widgets' stylesheet to the default display.
+pub fn load_css() {
+    let provider = CssProvider::new();
+    provider.load_from_data(include_bytes!("../dnd.css"));
+    let gdk_display = gdk::Display::default().unwrap();
+    StyleContext::add_provider_for_display(&gdk_display, &provider, 800);
+}
@@ -1,37 +1,163 @@
-use gtk::gdk::{ContentProvider, Rectangle, BUTTON_PRIMARY, BUTTON_SECONDARY};
-use gtk::glib::clone;
+use gtk::glib::{clone, OptionArg, OptionFlags, VariantDict};
 use gtk::prelude::*;
 use gtk::*;
-mod canvas_item;
-mod color_swatch;
-mod css_button;
-use canvas_item::*;
-use color_swatch::*;
-use css_button::*;
-pub static mut ITEM_ID: i32 = 0;
+use gtk4_dnd::export::{export_png, export_svg, render_document};
+use gtk4_dnd::{
+    Alignment, Canvas, CanvasView, Document, Inspector, Layers, PaletteBuilder, Stacking,
+};
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+/// Grid sizes offered in the menu, in pixels.
+const GRID_SIZES: [i32; 4] = [10, 20, 40, 80];
 
 fn main() {
     let application = gtk::Application::new(Some("com.github.gmg137.gtk4-dnd"), Default::default());
+    application.add_main_option(
+        "render",
+        glib::Char::from(b'r'),
+        OptionFlags::NONE,
+        OptionArg::Filename,
+        "Render a saved canvas to an image and exit",
+        Some("FILE"),
+    );
+    application.add_main_option(
+        "output",
+        glib::Char::from(b'o'),
+        OptionFlags::NONE,
+        OptionArg::Filename,
+        "Image written by --render, PNG or SVG (default: output.png)",
+        Some("FILE"),
+    );
+    application.add_main_option(
+        "width",
+        glib::Char::from(0),
+        OptionFlags::NONE,
+        OptionArg::Int,
+        "Width of the rendered image (default: the width of the items)",
+        Some("PIXELS"),
+    );
+    application.add_main_option(
+        "height",
+        glib::Char::from(0),
+        OptionFlags::NONE,
+        OptionArg::Int,
+        "Height of the rendered image (default: the height of the items)",
+        Some("PIXELS"),
+    );
+    application.connect_handle_local_options(|_, options| render_headless(options));
+    application.connect_startup(|_| gtk4_dnd::init());
     application.connect_activate(build_ui);
+    application.set_accels_for_action("win.open", &["<Control>o"]);
+    application.set_accels_for_action("win.save", &["<Control>s"]);
+    application.set_accels_for_action("win.save-as", &["<Control><Shift>s"]);
+    application.set_accels_for_action("win.undo", &["<Control>z"]);
+    application.set_accels_for_action("win.redo", &["<Control><Shift>z"]);
+    application.set_accels_for_action("win.copy", &["<Control>c"]);
+    application.set_accels_for_action("win.cut", &["<Control>x"]);
+    application.set_accels_for_action("win.paste", &["<Control>v"]);
+    application.set_accels_for_action("win.grid", &["<Control>apostrophe"]);
+    for (i, size) in GRID_SIZES.iter().enumerate() {
+        let action = format!("win.grid-size({})", size);
+        application.set_accels_for_action(&action, &[&format!("<Control><Alt>{}", i + 1)]);
+    }
+    application.set_accels_for_action("win.align-left", &["<Control><Shift>l"]);
+    application.set_accels_for_action("win.align-center", &["<Control><Shift>e"]);
+    application.set_accels_for_action("win.align-right", &["<Control><Shift>r"]);
+    application.set_accels_for_action("win.align-top", &["<Control><Shift>t"]);
+    application.set_accels_for_action("win.align-middle", &["<Control><Shift>m"]);
+    application.set_accels_for_action("win.align-bottom", &["<Control><Shift>b"]);
+    application.set_accels_for_action("win.distribute-horizontal", &["<Control><Shift>h"]);
+    application.set_accels_for_action("win.distribute-vertical", &["<Control><Shift>j"]);
+    application.set_accels_for_action("win.zoom-in", &["<Control>plus", "<Control>equal"]);
+    application.set_accels_for_action("win.zoom-out", &["<Control>minus"]);
+    application.set_accels_for_action("win.zoom-reset", &["<Control>0"]);
+    application.set_accels_for_action("win.zoom-fit", &["<Control>9"]);
+    application.set_accels_for_action("win.bring-to-front", &["<Control><Shift>Page_Up"]);
+    application.set_accels_for_action("win.raise", &["<Control>Page_Up"]);
+    application.set_accels_for_action("win.lower", &["<Control>Page_Down"]);
+    application.set_accels_for_action("win.send-to-back", &["<Control><Shift>Page_Down"]);
     application.run();
 }
 
-fn build_ui(application: &gtk::Application) {
-    let colors = [
-        "red", "green", "blue", "magenta", "orange", "gray", "black", "yellow", "white", "gray",
-        "brown", "pink", "cyan", "bisque", "gold", "maroon", "navy", "orchid", "olive", "peru",
-        "salmon", "silver", "wheat",
-    ];
+/// Handles `--render`: lays out the document offscreen and writes the image
+/// without creating a window. Returns -1 to continue with normal startup.
+fn render_headless(options: &VariantDict) -> i32 {
+    let input = match options.lookup::<PathBuf>("render") {
+        Ok(Some(input)) => input,
+        _ => return -1,
+    };
+    let output = options
+        .lookup::<PathBuf>("output")
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| PathBuf::from("output.png"));
+    let width = options.lookup::<i32>("width").ok().flatten();
+    let height = options.lookup::<i32>("height").ok().flatten();
+    if width.is_some_and(|width| width <= 0) || height.is_some_and(|height| height <= 0) {
+        eprintln!("--width and --height must be greater than 0");
+        return 1;
+    }
 
-    let provider = CssProvider::new();
-    provider.load_from_path("dnd.css");
-    let gdk_display = gdk::Display::default().unwrap();
-    StyleContext::add_provider_for_display(&gdk_display, &provider, 800);
+    if let Err(err) = gtk::init() {
+        eprintln!("Could not initialize GTK: {}", err);
+        return 1;
+    }
+    gtk4_dnd::load_css();
 
+    let result = Document::load(&input).and_then(|document| {
+        let size = |pixels: Option<i32>| pixels.map(|pixels| pixels as f32);
+        render_document(&document, size(width), size(height), &output)
+    });
+    match result {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("Could not render {}: {}", input.display(), err);
+            1
+        }
+    }
+}
+
+fn build_ui(application: &gtk::Application) {
     let window = gtk::ApplicationWindow::new(application);
 
     window.set_title(Some("Drag-and-Drop"));
-    window.set_default_size(640, 480);
+    window.set_default_size(900, 480);
+
+    let header = HeaderBar::new();
+    let button = Button::with_label("Open…");
+    button.set_action_name(Some("win.open"));
+    header.pack_start(&button);
+    let menu = gio::Menu::new();
+    menu.append(Some("Zoom to Fit"), Some("win.zoom-fit"));
+    menu.append(Some("Actual Size"), Some("win.zoom-reset"));
+    menu.append(Some("Snap to Grid"), Some("win.grid"));
+    let sizes = gio::Menu::new();
+    for size in GRID_SIZES {
+        let label = format!("{} px", size);
+        sizes.append(Some(&label), Some(&format!("win.grid-size({})", size)));
+    }
+    menu.append_submenu(Some("Grid Size"), &sizes);
+    menu.append(Some("Raise on Click"), Some("win.auto-raise"));
+    let button = MenuButton::new();
+    button.set_icon_name("open-menu-symbolic");
+    button.set_menu_model(Some(&menu));
+    header.pack_end(&button);
+    let menu = gio::Menu::new();
+    menu.append(Some("Export as PNG…"), Some("win.export-png"));
+    menu.append(Some("Export as SVG…"), Some("win.export-svg"));
+    let button = MenuButton::new();
+    button.set_label("Export");
+    button.set_menu_model(Some(&menu));
+    header.pack_end(&button);
+    let button = Button::with_label("Save As…");
+    button.set_action_name(Some("win.save-as"));
+    header.pack_end(&button);
+    let button = Button::with_label("Save");
+    button.set_action_name(Some("win.save"));
+    header.pack_end(&button);
+    window.set_titlebar(Some(&header));
 
     let box1 = Box::new(Orientation::Vertical, 0);
     window.set_child(Some(&box1));
@@ -39,18 +165,29 @@ fn build_ui(application: &gtk::Application) {
     let box2 = Box::new(Orientation::Horizontal, 0);
     box1.append(&box2);
 
-    let canvas = canvas_new();
-    box2.append(&canvas);
-
-    unsafe {
-        ITEM_ID = 0;
-    }
+    let canvas = Canvas::new();
+    let view = CanvasView::new(&canvas);
+    let scrolled = ScrolledWindow::new();
+    scrolled.set_child(Some(&view));
+    scrolled.set_hexpand(true);
+    scrolled.set_vexpand(true);
+    box2.append(&scrolled);
+    box2.append(&Separator::new(Orientation::Vertical));
+    let sidebar = Box::new(Orientation::Vertical, 0);
+    sidebar.append(&Inspector::new(&canvas));
+    sidebar.append(&Separator::new(Orientation::Horizontal));
+    sidebar.append(&Layers::new(&canvas));
+    box2.append(&sidebar);
+    add_document_actions(&window, &canvas);
+    add_history_actions(&window, &canvas);
+    add_clipboard_actions(&window, &canvas);
+    add_export_actions(&window, &canvas);
+    add_view_actions(&window, &view);
+    add_arrange_actions(&window, &canvas);
 
     let (mut x, mut y) = (40., 40.);
     for _ in 0..4 {
-        let item = CanvasItem::new();
-        canvas.put(&item, x, y);
-        item.apply_transform();
+        canvas.new_item(x, y);
         x += 150.;
         y += 100.;
     }
@@ -58,171 +195,344 @@ fn build_ui(application: &gtk::Application) {
     let separator = Separator::new(Orientation::Horizontal);
     box1.append(&separator);
 
-    let sw = ScrolledWindow::new();
-    sw.set_policy(PolicyType::Automatic, PolicyType::Never);
-    box1.append(&sw);
+    box1.append(&PaletteBuilder::new().build());
 
-    let box3 = Box::new(Orientation::Horizontal, 0);
-    box3.add_css_class("linked");
-    sw.set_child(Some(&box3));
+    window.show();
+}
 
-    for i in colors {
-        box3.append(&ColorSwatch::new(i));
-    }
+fn add_document_actions(window: &ApplicationWindow, canvas: &Canvas) {
+    let path: Rc<RefCell<Option<PathBuf>>> = Rc::default();
+
+    let action = gio::SimpleAction::new("open", None);
+    action.connect_activate(clone!(@weak window, @weak canvas, @strong path => move |_, _| {
+        let dialog = FileChooserNative::new(
+            Some("Open Canvas"),
+            Some(&window),
+            FileChooserAction::Open,
+            Some("_Open"),
+            Some("_Cancel"),
+        );
+        dialog.add_filter(&document_filter());
+        dialog.connect_response(clone!(@weak window, @weak canvas, @strong path => move |dialog, response| {
+            if response != ResponseType::Accept {
+                return;
+            }
+            if let Some(file) = dialog.file().and_then(|file| file.path()) {
+                match Document::load(&file) {
+                    Ok(document) => {
+                        document.populate(&canvas);
+                        canvas.history().borrow_mut().clear();
+                        set_document_path(&window, &path, file);
+                    }
+                    Err(err) => show_error(&window, &format!("Could not open {}: {}", file.display(), err)),
+                }
+            }
+        }));
+        dialog.show();
+        unsafe {
+            window.set_data("file-chooser", dialog);
+        }
+    }));
+    window.add_action(&action);
+
+    let action = gio::SimpleAction::new("save", None);
+    action.connect_activate(
+        clone!(@weak window, @weak canvas, @strong path => move |_, _| {
+            let file = path.borrow().clone();
+            match file {
+                Some(file) => save_document(&window, &canvas, &path, file),
+                None => save_document_as(&window, &canvas, &path),
+            }
+        }),
+    );
+    window.add_action(&action);
+
+    let action = gio::SimpleAction::new("save-as", None);
+    action.connect_activate(
+        clone!(@weak window, @weak canvas, @strong path => move |_, _| {
+            save_document_as(&window, &canvas, &path);
+        }),
+    );
+    window.add_action(&action);
+}
 
-    box3.append(&CssButton::new("rainbow1"));
-    box3.append(&CssButton::new("rainbow2"));
-    box3.append(&CssButton::new("rainbow3"));
+fn add_history_actions(window: &ApplicationWindow, canvas: &Canvas) {
+    let action = gio::SimpleAction::new("undo", None);
+    action.connect_activate(clone!(@weak canvas => move |_, _| canvas.undo()));
+    window.add_action(&action);
 
-    window.show();
+    let action = gio::SimpleAction::new("redo", None);
+    action.connect_activate(clone!(@weak canvas => move |_, _| canvas.redo()));
+    window.add_action(&action);
 }
 
-fn canvas_new() -> Fixed {
-    let canvas = Fixed::new();
-    canvas.set_hexpand(true);
-    canvas.set_vexpand(true);
-
-    let source = DragSource::new();
-    source.set_actions(gdk::DragAction::MOVE);
-    source.connect_prepare(|source, x, y| {
-        let canvas = source.widget();
-        let item = canvas.pick(x, y, PickFlags::DEFAULT).unwrap();
-
-        if let Some(item) = item.ancestor(CanvasItem::static_type()) {
-            unsafe {
-                canvas.set_data::<Widget>("dragged-item", item.to_owned());
-                return Some(ContentProvider::for_value(&item.to_value()));
+fn add_clipboard_actions(window: &ApplicationWindow, canvas: &Canvas) {
+    let action = gio::SimpleAction::new("copy", None);
+    action.connect_activate(clone!(@weak window, @weak canvas => move |_, _| {
+        match focused_text(&window) {
+            Some(text) => {
+                let _ = text.activate_action("clipboard.copy", None);
+            }
+            None => {
+                canvas.copy_selection();
             }
         }
-        None
-    });
-    source.connect_drag_begin(|source, _drag| {
-        let fixed_widget = source.widget();
-        unsafe {
-            let item_label = fixed_widget.data::<Label>("dragged-item").unwrap();
-            let item_label = item_label.as_ref();
-
-            let paintable = WidgetPaintable::new(Some(item_label));
+    }));
+    window.add_action(&action);
 
-            if let Some(canvas_item) = item_label.ancestor(CanvasItem::static_type()) {
-                let canvas_item: CanvasItem = canvas_item.downcast().unwrap();
-                source.set_icon(Some(&paintable), canvas_item.r(), canvas_item.r());
-                canvas_item.set_opacity(0.3);
+    let action = gio::SimpleAction::new("cut", None);
+    action.connect_activate(clone!(@weak window, @weak canvas => move |_, _| {
+        match focused_text(&window) {
+            Some(text) => {
+                let _ = text.activate_action("clipboard.cut", None);
             }
+            None => canvas.cut_selection(),
         }
-    });
-    source.connect_drag_end(move |source, _drag, _| unsafe {
-        let canvas = source.widget();
-        if let Some(item) = canvas.steal_data::<Widget>("dragged-item") {
-            item.set_opacity(1.0);
+    }));
+    window.add_action(&action);
+
+    let action = gio::SimpleAction::new("paste", None);
+    action.connect_activate(clone!(@weak window, @weak canvas => move |_, _| {
+        match focused_text(&window) {
+            Some(text) => {
+                let _ = text.activate_action("clipboard.paste", None);
+            }
+            None => canvas.paste(),
         }
-    });
-    source.connect_drag_cancel(|_, _, _| false);
-    canvas.add_controller(&source);
-
-    let dest = DropTarget::new(Widget::static_type(), gdk::DragAction::MOVE);
-    dest.connect_drop(|_target, value, x, y| {
-        let item = value.get::<Widget>().unwrap();
-        let item: CanvasItem = item.downcast().unwrap();
-
-        let canvas = item.parent().unwrap();
-        let last_child = canvas.last_child().unwrap();
-        if item != last_child {
-            item.insert_after(&canvas, Some(&last_child));
+    }));
+    window.add_action(&action);
+}
+
+fn add_view_actions(window: &ApplicationWindow, view: &CanvasView) {
+    let canvas = view.canvas();
+    for (name, zoom) in [
+        ("zoom-in", CanvasView::zoom_in as fn(&CanvasView)),
+        ("zoom-out", CanvasView::zoom_out),
+        ("zoom-reset", |view| view.set_zoom(1.)),
+        ("zoom-fit", CanvasView::zoom_to_fit),
+    ] {
+        let action = gio::SimpleAction::new(name, None);
+        action.connect_activate(clone!(@weak view => move |_, _| zoom(&view)));
+        window.add_action(&action);
+    }
+
+    let action = gio::SimpleAction::new_stateful("grid", None, &canvas.has_grid().to_variant());
+    action.connect_activate(clone!(@weak canvas => move |action, _| {
+        let grid = !canvas.has_grid();
+        canvas.set_grid(grid);
+        action.set_state(&grid.to_variant());
+    }));
+    window.add_action(&action);
+
+    let state = (canvas.grid_size().round() as i32).to_variant();
+    let action = gio::SimpleAction::new_stateful("grid-size", Some(glib::VariantTy::INT32), &state);
+    action.connect_activate(clone!(@weak canvas => move |action, size| {
+        if let Some(size) = size.and_then(|size| size.get::<i32>()) {
+            canvas.set_grid_size(size as f64);
+            action.set_state(&size.to_variant());
         }
-        canvas
-            .downcast::<Fixed>()
-            .unwrap()
-            .move_(&item, x - item.r() as f64, y - item.r() as f64);
-        true
-    });
-    canvas.add_controller(&dest);
-
-    let gesture = GestureClick::new();
-    gesture.set_button(0);
-    gesture.connect_pressed(clone!(@weak canvas =>move |gesture, _n_press, x, y| {
-        let widget = gesture.widget();
-        let child = widget.pick(x, y, PickFlags::DEFAULT).unwrap();
-        let child = child.ancestor(child.type_()).unwrap();
-
-        if gesture.current_button() == BUTTON_SECONDARY {
-            let menu = Popover::new();
-            menu.set_parent(&widget);
-            menu.set_has_arrow(false);
-            let rect = Rectangle::new(x as i32, y as i32, 1, 1);
-            menu.set_pointing_to(Some(&rect));
-            let box1 = Box::new(Orientation::Vertical, 0);
-            menu.set_child(Some(&box1));
-
-            let item = Button::with_label("New");
-            item.set_has_frame(false);
-            item.connect_clicked(clone!(@weak canvas =>move |button| {
-                let popover = button.ancestor(Popover::static_type()).unwrap();
-                let popover: Popover = popover.downcast().unwrap();
-                let rect = popover.pointing_to().1;
-
-                let item = CanvasItem::new();
-                canvas.put(&item, rect.x() as f64, rect.y() as f64);
-                item.apply_transform();
-
-                popover.popdown();
-            }));
-            box1.append(&item);
-
-            let item = Separator::new(Orientation::Horizontal);
-            box1.append(&item);
-
-            let item = Button::with_label("Edit");
-            item.set_has_frame(false);
-            item.set_sensitive(child != widget);
-            item.connect_clicked(clone!(@weak child => move|button|{
-                let item = child.ancestor(CanvasItem::static_type()).unwrap();
-                let item: CanvasItem  = item.downcast().unwrap();
-
-                let popover = button.ancestor(Popover::static_type()).unwrap();
-                let popover: Popover = popover.downcast().unwrap();
-                popover.popdown();
-
-                if !item.is_editing() {
-                    item.start_editing();
+    }));
+    window.add_action(&action);
+
+    let state = canvas.has_auto_raise().to_variant();
+    let action = gio::SimpleAction::new_stateful("auto-raise", None, &state);
+    action.connect_activate(clone!(@weak canvas => move |action, _| {
+        let auto_raise = !canvas.has_auto_raise();
+        canvas.set_auto_raise(auto_raise);
+        action.set_state(&auto_raise.to_variant());
+    }));
+    window.add_action(&action);
+}
+
+fn add_arrange_actions(window: &ApplicationWindow, canvas: &Canvas) {
+    for (name, alignment) in [
+        ("align-left", Alignment::Left),
+        ("align-center", Alignment::Center),
+        ("align-right", Alignment::Right),
+        ("align-top", Alignment::Top),
+        ("align-middle", Alignment::Middle),
+        ("align-bottom", Alignment::Bottom),
+    ] {
+        let action = gio::SimpleAction::new(name, None);
+        action.connect_activate(clone!(@weak canvas => move |_, _| {
+            canvas.align_selection(alignment);
+        }));
+        window.add_action(&action);
+    }
+
+    for (name, orientation) in [
+        ("distribute-horizontal", Orientation::Horizontal),
+        ("distribute-vertical", Orientation::Vertical),
+    ] {
+        let action = gio::SimpleAction::new(name, None);
+        action.connect_activate(clone!(@weak canvas => move |_, _| {
+            canvas.distribute_selection(orientation);
+        }));
+        window.add_action(&action);
+    }
+
+    for (name, stacking) in [
+        ("bring-to-front", Stacking::Front),
+        ("raise", Stacking::Raise),
+        ("lower", Stacking::Lower),
+        ("send-to-back", Stacking::Back),
+    ] {
+        let action = gio::SimpleAction::new(name, None);
+        action.connect_activate(clone!(@weak canvas => move |_, _| {
+            canvas.restack_selection(stacking);
+        }));
+        window.add_action(&action);
+    }
+}
+
+fn add_export_actions(window: &ApplicationWindow, canvas: &Canvas) {
+    let action = gio::SimpleAction::new("export-png", None);
+    action.connect_activate(clone!(@weak window, @weak canvas => move |_, _| {
+        let dialog = FileChooserNative::new(
+            Some("Export as PNG"),
+            Some(&window),
+            FileChooserAction::Save,
+            Some("_Export"),
+            Some("_Cancel"),
+        );
+        let filter = FileFilter::new();
+        filter.set_name(Some("PNG images"));
+        filter.add_mime_type("image/png");
+        dialog.add_filter(&filter);
+        dialog.add_choice("scale", "Scale", &[("1", "1×"), ("2", "2×"), ("3", "3×"), ("4", "4×")]);
+        dialog.set_choice("scale", "1");
+        dialog.set_current_name("canvas.png");
+        dialog.connect_response(clone!(@weak window, @weak canvas => move |dialog, response| {
+            if response != ResponseType::Accept {
+                return;
+            }
+            let scale = dialog
+                .choice("scale")
+                .and_then(|scale| scale.parse().ok())
+                .unwrap_or(1.);
+            if let Some(file) = dialog.file().and_then(|file| file.path()) {
+                if let Err(err) = export_png(&canvas, scale, &file) {
+                    show_error(&window, &format!("Could not export {}: {}", file.display(), err));
                 }
-            }));
-            box1.append(&item);
-
-            let item = Separator::new(Orientation::Horizontal);
-            box1.append(&item);
-
-            let item = Button::with_label("Delete");
-            item.set_has_frame(false);
-            item.set_sensitive(child != widget);
-            item.connect_clicked(clone!(@weak child => move|button|{
-                let canvas = child.parent().unwrap();
-                let canvas: Fixed  = canvas.downcast().unwrap();
-                canvas.remove(&child);
-                let popover = button.ancestor(Popover::static_type()).unwrap();
-                let popover: Popover = popover.downcast().unwrap();
-                popover.popdown();
-            }));
-            box1.append(&item);
-
-            menu.popup();
+            }
+        }));
+        dialog.show();
+        unsafe {
+            window.set_data("file-chooser", dialog);
         }
     }));
-
-    gesture.connect_released(|gesture, _, x, y| {
-        let widget = gesture.widget();
-        let child = widget.pick(x, y, PickFlags::DEFAULT).unwrap();
-        if let Some(item) = child.ancestor(CanvasItem::static_type()) {
-            if gesture.current_button() == BUTTON_PRIMARY {
-                let item: CanvasItem = item.downcast().unwrap();
-                if item.is_editing() {
-                    item.stop_editing();
-                } else {
-                    item.start_editing();
+    window.add_action(&action);
+
+    let action = gio::SimpleAction::new("export-svg", None);
+    action.connect_activate(clone!(@weak window, @weak canvas => move |_, _| {
+        let dialog = FileChooserNative::new(
+            Some("Export as SVG"),
+            Some(&window),
+            FileChooserAction::Save,
+            Some("_Export"),
+            Some("_Cancel"),
+        );
+        let filter = FileFilter::new();
+        filter.set_name(Some("SVG images"));
+        filter.add_mime_type("image/svg+xml");
+        dialog.add_filter(&filter);
+        dialog.set_current_name("canvas.svg");
+        dialog.connect_response(clone!(@weak window, @weak canvas => move |dialog, response| {
+            if response != ResponseType::Accept {
+                return;
+            }
+            if let Some(file) = dialog.file().and_then(|file| file.path()) {
+                if let Err(err) = export_svg(&canvas, &file) {
+                    show_error(&window, &format!("Could not export {}: {}", file.display(), err));
                 }
             }
+        }));
+        dialog.show();
+        unsafe {
+            window.set_data("file-chooser", dialog);
         }
-    });
-    canvas.add_controller(&gesture);
-    canvas
+    }));
+    window.add_action(&action);
+}
+
+fn save_document_as(
+    window: &ApplicationWindow,
+    canvas: &Canvas,
+    path: &Rc<RefCell<Option<PathBuf>>>,
+) {
+    let dialog = FileChooserNative::new(
+        Some("Save Canvas"),
+        Some(window),
+        FileChooserAction::Save,
+        Some("_Save"),
+        Some("_Cancel"),
+    );
+    dialog.add_filter(&document_filter());
+    dialog.set_current_name("canvas.json");
+    dialog.connect_response(
+        clone!(@weak window, @weak canvas, @strong path => move |dialog, response| {
+            if response != ResponseType::Accept {
+                return;
+            }
+            if let Some(file) = dialog.file().and_then(|file| file.path()) {
+                save_document(&window, &canvas, &path, file);
+            }
+        }),
+    );
+    dialog.show();
+    unsafe {
+        window.set_data("file-chooser", dialog);
+    }
+}
+
+fn save_document(
+    window: &ApplicationWindow,
+    canvas: &Canvas,
+    path: &Rc<RefCell<Option<PathBuf>>>,
+    file: PathBuf,
+) {
+    match Document::from_canvas(canvas).save(&file) {
+        Ok(()) => set_document_path(window, path, file),
+        Err(err) => show_error(
+            window,
+            &format!("Could not save {}: {}", file.display(), err),
+        ),
+    }
+}
+
+fn set_document_path(
+    window: &ApplicationWindow,
+    path: &Rc<RefCell<Option<PathBuf>>>,
+    file: PathBuf,
+) {
+    if let Some(name) = file.file_name() {
+        window.set_title(Some(&format!("Drag-and-Drop — {}", name.to_string_lossy())));
+    }
+    path.replace(Some(file));
+}
+
+fn document_filter() -> FileFilter {
+    let filter = FileFilter::new();
+    filter.set_name(Some("Canvas documents"));
+    filter.add_mime_type("application/json");
+    filter.add_pattern("*.json");
+    filter
+}
+
+fn show_error(window: &ApplicationWindow, message: &str) {
+    let dialog = MessageDialog::new(
+        Some(window),
+        DialogFlags::MODAL | DialogFlags::DESTROY_WITH_PARENT,
+        MessageType::Error,
+        ButtonsType::Close,
+        message,
+    );
+    dialog.connect_response(|dialog, _| dialog.destroy());
+    dialog.show();
+}
+
+/// The focused text widget of `window`, which should handle clipboard
+/// shortcuts itself while an item is being edited.
+fn focused_text(window: &ApplicationWindow) -> Option<Widget> {
+    window.focus().filter(|widget| widget.is::<gtk::Editable>())
 }
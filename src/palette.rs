@@ -0,0 +1,80 @@
+//
+// palette.rs
+// Copyright (C) 2022 gmg137 <gmg137 AT live.com>
+// Distributed under terms of the GPL-3.0-or-later license.
+//
+use gtk::prelude::*;
+use gtk::{Box, Orientation, PolicyType, ScrolledWindow};
+
+use crate::color_swatch::ColorSwatch;
+use crate::css_button::CssButton;
+
+pub const DEFAULT_COLORS: [&str; 23] = [
+    "red", "green", "blue", "magenta", "orange", "gray", "black", "yellow", "white", "gray",
+    "brown", "pink", "cyan", "bisque", "gold", "maroon", "navy", "orchid", "olive", "peru",
+    "salmon", "silver", "wheat",
+];
+
+pub const DEFAULT_STYLES: [&str; 3] = ["rainbow1", "rainbow2", "rainbow3"];
+
+/// Builds a strip of `ColorSwatch`es followed by `CssButton`s that can be
+/// dragged onto canvas items.
+#[derive(Debug, Clone)]
+pub struct PaletteBuilder {
+    colors: Vec<String>,
+    styles: Vec<String>,
+    orientation: Orientation,
+}
+
+impl Default for PaletteBuilder {
+    fn default() -> Self {
+        Self {
+            colors: DEFAULT_COLORS.iter().map(|c| c.to_string()).collect(),
+            styles: DEFAULT_STYLES.iter().map(|s| s.to_string()).collect(),
+            orientation: Orientation::Horizontal,
+        }
+    }
+}
+
+impl PaletteBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the colours, given as anything `gdk::RGBA::parse` accepts.
+    pub fn colors(mut self, colors: &[&str]) -> Self {
+        self.colors = colors.iter().map(|c| c.to_string()).collect();
+        self
+    }
+
+    /// Replaces the style classes, which must be defined by a loaded stylesheet.
+    pub fn styles(mut self, styles: &[&str]) -> Self {
+        self.styles = styles.iter().map(|s| s.to_string()).collect();
+        self
+    }
+
+    pub fn orientation(mut self, orientation: Orientation) -> Self {
+        self.orientation = orientation;
+        self
+    }
+
+    pub fn build(self) -> ScrolledWindow {
+        let sw = ScrolledWindow::new();
+        match self.orientation {
+            Orientation::Vertical => sw.set_policy(PolicyType::Never, PolicyType::Automatic),
+            _ => sw.set_policy(PolicyType::Automatic, PolicyType::Never),
+        }
+
+        let box1 = Box::new(self.orientation, 0);
+        box1.add_css_class("linked");
+        sw.set_child(Some(&box1));
+
+        for color in &self.colors {
+            box1.append(&ColorSwatch::new(color));
+        }
+        for style in &self.styles {
+            box1.append(&CssButton::new(style));
+        }
+        sw
+    }
+}
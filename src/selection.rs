@@ -0,0 +1,94 @@
+//
+// selection.rs
+// Copyright (C) 2022 gmg137 <gmg137 AT live.com>
+// Distributed under terms of the GPL-3.0-or-later license.
+//
+use gtk::gdk::ModifierType;
+use gtk::graphene::Rect;
+use gtk::prelude::*;
+use gtk::{Box, EventSequenceState, GestureDrag, Orientation, PickFlags};
+
+use crate::canvas::Canvas;
+use crate::canvas_item::CanvasItem;
+
+/// Selects `item` unless it already is, in which case the selection is kept
+/// so the item can act on the whole group.
+pub fn select_for_action(canvas: &Canvas, item: &CanvasItem) -> Vec<CanvasItem> {
+    if !item.is_selected() {
+        canvas.select_only(Some(item));
+    }
+    canvas.selected_items()
+}
+
+/// Lets the user select items by dragging a rectangle over empty canvas.
+/// Holding Shift or Ctrl adds to the current selection.
+pub fn add_rubberband(canvas: &Canvas) {
+    let gesture = GestureDrag::new();
+    gesture.connect_drag_begin(|gesture, x, y| {
+        let canvas: Canvas = gesture.widget().downcast().unwrap();
+        let picked = canvas.pick(x, y, PickFlags::DEFAULT);
+        if !canvas.has_rubberband() || picked.as_ref() != Some(canvas.upcast_ref()) {
+            gesture.set_state(EventSequenceState::Denied);
+            return;
+        }
+
+        let band = Box::new(Orientation::Horizontal, 0);
+        band.add_css_class("rubberband");
+        band.set_can_target(false);
+        canvas.put(&band, x, y);
+        unsafe {
+            canvas.set_data("rubberband", band);
+        }
+    });
+
+    gesture.connect_drag_update(|gesture, dx, dy| {
+        let canvas: Canvas = gesture.widget().downcast().unwrap();
+        let (x, y) = gesture.start_point().unwrap();
+        let rect = band_rect(x, y, dx, dy);
+        unsafe {
+            if let Some(band) = canvas.data::<Box>("rubberband") {
+                let band = band.as_ref();
+                canvas.move_(band, rect.x() as f64, rect.y() as f64);
+                band.set_size_request(rect.width() as i32, rect.height() as i32);
+            }
+        }
+    });
+
+    gesture.connect_drag_end(|gesture, dx, dy| {
+        let canvas: Canvas = gesture.widget().downcast().unwrap();
+        let band = unsafe { canvas.steal_data::<Box>("rubberband") };
+        let band = match band {
+            Some(band) => band,
+            None => return,
+        };
+        canvas.remove(&band);
+
+        let (x, y) = gesture.start_point().unwrap();
+        let rect = band_rect(x, y, dx, dy);
+        let extend = gesture
+            .current_event_state()
+            .intersects(ModifierType::SHIFT_MASK | ModifierType::CONTROL_MASK);
+        let items: Vec<CanvasItem> = canvas
+            .items()
+            .into_iter()
+            .filter(|item| {
+                let hit = item
+                    .bounds(&canvas)
+                    .and_then(|bounds| bounds.intersection(&rect))
+                    .is_some();
+                hit || (extend && item.is_selected())
+            })
+            .collect();
+        canvas.select_items(&items);
+    });
+    canvas.add_controller(&gesture);
+}
+
+fn band_rect(x: f64, y: f64, dx: f64, dy: f64) -> Rect {
+    Rect::new(
+        (x + dx.min(0.)) as f32,
+        (y + dy.min(0.)) as f32,
+        dx.abs() as f32,
+        dy.abs() as f32,
+    )
+}
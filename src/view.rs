@@ -0,0 +1,521 @@
+//
+// view.rs
+// Copyright (C) 2022 gmg137 <gmg137 AT live.com>
+// Distributed under terms of the GPL-3.0-or-later license.
+//
+use gtk::gdk::{Key, ModifierType, BUTTON_MIDDLE};
+use gtk::glib::clone;
+use gtk::glib::{
+    ParamFlags, ParamSpec, ParamSpecDouble, ParamSpecOverride, SignalHandlerId, Value,
+};
+use gtk::graphene::{Point, Rect};
+use gtk::prelude::*;
+use gtk::subclass::prelude::*;
+use gtk::{glib, gsk};
+use gtk::{
+    Adjustment, EventControllerKey, EventControllerMotion, EventControllerScroll,
+    EventControllerScrollFlags, EventSequenceState, GestureDrag, GestureZoom, Orientation,
+    Overflow, PropagationPhase, ScrollablePolicy,
+};
+use once_cell::sync::Lazy;
+use std::cell::{Cell, RefCell};
+
+use crate::canvas::Canvas;
+
+/// Smallest and largest zoom factor.
+pub const MIN_ZOOM: f64 = 0.1;
+pub const MAX_ZOOM: f64 = 8.;
+
+/// Factor one zoom in or out step, or one notch of Ctrl+scroll, changes the
+/// zoom by.
+const ZOOM_STEP: f64 = 1.25;
+
+/// Room in canvas pixels that stays scrollable around the items, so they
+/// can be dragged further out.
+const MARGIN: f32 = 200.;
+
+/// Padding in canvas pixels kept around the items by `zoom_to_fit`.
+const FIT_PADDING: f32 = 20.;
+
+/// Width of the band along the view edges in which a drag scrolls, and the
+/// speed in pixels per second reached at the very edge.
+const AUTOSCROLL_EDGE: f64 = 40.;
+const AUTOSCROLL_SPEED: f64 = 900.;
+
+glib::wrapper! {
+    pub struct CanvasView(ObjectSubclass<ExCanvasView>)
+        @extends gtk::Widget,
+        @implements gtk::Scrollable;
+}
+
+impl CanvasView {
+    /// Shows `canvas` zoomed and scrolled. Put the view into a
+    /// `gtk::ScrolledWindow` to get scrollbars.
+    ///
+    /// Ctrl+scroll and pinching zoom, dragging with the middle button or
+    /// with Space held pans.
+    pub fn new(canvas: &Canvas) -> Self {
+        let view: Self = glib::Object::new(&[]).expect("Failed to create CanvasView");
+        canvas.set_parent(&view);
+        *view.imp().canvas.borrow_mut() = Some(canvas.clone());
+        view
+    }
+
+    pub fn canvas(&self) -> Canvas {
+        self.imp().canvas.borrow().clone().unwrap()
+    }
+
+    pub fn zoom(&self) -> f64 {
+        self.imp().zoom.get()
+    }
+
+    /// Zooms around the centre of the view.
+    pub fn set_zoom(&self, zoom: f64) {
+        self.zoom_at(zoom, self.width() as f64 / 2., self.height() as f64 / 2.);
+    }
+
+    /// Zooms so the canvas point under `(x, y)`, in view coordinates, stays
+    /// where it is.
+    pub fn zoom_at(&self, zoom: f64, x: f64, y: f64) {
+        let imp = self.imp();
+        let zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+        let old = imp.zoom.get();
+        if zoom == old {
+            return;
+        }
+        let (left, top) = self.scroll_position();
+        imp.anchor.set(Some(Anchor {
+            canvas: ((left + x) / old, (top + y) / old),
+            view: (x, y),
+        }));
+        imp.zoom.set(zoom);
+        self.queue_resize();
+        self.notify("zoom");
+    }
+
+    pub fn zoom_in(&self) {
+        self.set_zoom(self.zoom() * ZOOM_STEP);
+    }
+
+    pub fn zoom_out(&self) {
+        self.set_zoom(self.zoom() / ZOOM_STEP);
+    }
+
+    /// Zooms and scrolls so every item is visible.
+    pub fn zoom_to_fit(&self) {
+        let bounds = match self.canvas().items_bounds() {
+            Some(bounds) => bounds.inset_r(-FIT_PADDING, -FIT_PADDING),
+            None => return,
+        };
+        let (width, height) = (self.width() as f64, self.height() as f64);
+        let zoom = (width / bounds.width() as f64)
+            .min(height / bounds.height() as f64)
+            .clamp(MIN_ZOOM, MAX_ZOOM);
+        let centre = bounds.center();
+        let imp = self.imp();
+        imp.anchor.set(Some(Anchor {
+            canvas: (centre.x() as f64, centre.y() as f64),
+            view: (width / 2., height / 2.),
+        }));
+        if zoom != imp.zoom.get() {
+            imp.zoom.set(zoom);
+            self.notify("zoom");
+        }
+        self.queue_resize();
+    }
+
+    /// Scrolls continuously while a drag at `(x, y)`, in view coordinates,
+    /// is near an edge, faster the closer it gets. Moving away from the
+    /// edges or calling [`stop_autoscroll`](Self::stop_autoscroll) stops it.
+    pub fn autoscroll(&self, x: f64, y: f64) {
+        let speed = |position: f64, size: f64| {
+            let depth = if position < AUTOSCROLL_EDGE {
+                position - AUTOSCROLL_EDGE
+            } else if position > size - AUTOSCROLL_EDGE {
+                position - (size - AUTOSCROLL_EDGE)
+            } else {
+                0.
+            };
+            (depth / AUTOSCROLL_EDGE).clamp(-1., 1.) * AUTOSCROLL_SPEED
+        };
+        let velocity = (
+            speed(x, self.width() as f64),
+            speed(y, self.height() as f64),
+        );
+        let imp = self.imp();
+        imp.autoscroll.set(velocity);
+        if velocity == (0., 0.) || imp.frame_time.get().is_some() {
+            return;
+        }
+
+        imp.frame_time.set(Some(0));
+        self.add_tick_callback(|view, clock| {
+            let imp = view.imp();
+            let (dx, dy) = imp.autoscroll.get();
+            if (dx, dy) == (0., 0.) {
+                imp.frame_time.set(None);
+                return Continue(false);
+            }
+            let now = clock.frame_time();
+            let last = imp.frame_time.replace(Some(now)).filter(|&last| last > 0);
+            if let Some(last) = last {
+                let seconds = (now - last) as f64 / 1_000_000.;
+                let (left, top) = view.scroll_position();
+                view.scroll_into_space(left + dx * seconds, top + dy * seconds);
+            }
+            Continue(true)
+        });
+    }
+
+    pub fn stop_autoscroll(&self) {
+        self.imp().autoscroll.set((0., 0.));
+    }
+
+    /// Converts a point in view coordinates to canvas coordinates.
+    pub fn to_canvas(&self, x: f64, y: f64) -> (f64, f64) {
+        let (left, top) = self.scroll_position();
+        let zoom = self.zoom();
+        ((left + x) / zoom, (top + y) / zoom)
+    }
+
+    fn scroll_position(&self) -> (f64, f64) {
+        let imp = self.imp();
+        let value = |adjustment: &RefCell<Option<Adjustment>>| {
+            adjustment.borrow().as_ref().map_or(0., Adjustment::value)
+        };
+        (value(&imp.hadjustment), value(&imp.vadjustment))
+    }
+
+    fn scroll_to(&self, left: f64, top: f64) {
+        let imp = self.imp();
+        let hadjustment = imp.hadjustment.borrow().clone();
+        let vadjustment = imp.vadjustment.borrow().clone();
+        if let Some(adjustment) = hadjustment {
+            adjustment.set_value(left);
+        }
+        if let Some(adjustment) = vadjustment {
+            adjustment.set_value(top);
+        }
+    }
+
+    /// Like `scroll_to`, but first grows the scrollable area to reach
+    /// `(left, top)` instead of stopping at its edge. The next allocation
+    /// keeps the new space since it lies in the visible area.
+    fn scroll_into_space(&self, left: f64, top: f64) {
+        let imp = self.imp();
+        for (adjustment, value) in [(&imp.hadjustment, left), (&imp.vadjustment, top)] {
+            let adjustment = adjustment.borrow().clone();
+            if let Some(adjustment) = adjustment {
+                if value < adjustment.lower() {
+                    adjustment.set_lower(value);
+                }
+                if value + adjustment.page_size() > adjustment.upper() {
+                    adjustment.set_upper(value + adjustment.page_size());
+                }
+            }
+        }
+        self.scroll_to(left, top);
+    }
+
+    fn set_adjustment(&self, orientation: Orientation, adjustment: Option<Adjustment>) {
+        let imp = self.imp();
+        let adjustment = adjustment.unwrap_or_else(|| Adjustment::new(0., 0., 0., 0., 0., 0.));
+        let handler = adjustment.connect_value_changed(clone!(@weak self as view => move |_| {
+            view.queue_allocate();
+        }));
+        let (slot, handler_slot) = match orientation {
+            Orientation::Horizontal => (&imp.hadjustment, &imp.hadjustment_handler),
+            _ => (&imp.vadjustment, &imp.vadjustment_handler),
+        };
+        let old = slot.replace(Some(adjustment));
+        if let (Some(old), Some(old_handler)) = (old, handler_slot.replace(Some(handler))) {
+            old.disconnect(old_handler);
+        }
+        self.queue_allocate();
+    }
+}
+
+/// Canvas point to show at a view point after the next allocation.
+#[derive(Debug, Clone, Copy)]
+struct Anchor {
+    canvas: (f64, f64),
+    view: (f64, f64),
+}
+
+#[derive(Debug)]
+pub struct ExCanvasView {
+    canvas: RefCell<Option<Canvas>>,
+    hadjustment: RefCell<Option<Adjustment>>,
+    vadjustment: RefCell<Option<Adjustment>>,
+    /// The `value-changed` handlers connected to the adjustments above.
+    hadjustment_handler: RefCell<Option<SignalHandlerId>>,
+    vadjustment_handler: RefCell<Option<SignalHandlerId>>,
+    hscroll_policy: Cell<ScrollablePolicy>,
+    vscroll_policy: Cell<ScrollablePolicy>,
+    zoom: Cell<f64>,
+    anchor: Cell<Option<Anchor>>,
+    pointer: Cell<(f64, f64)>,
+    space: Cell<bool>,
+    pan_start: Cell<(f64, f64)>,
+    pinch_start: Cell<f64>,
+    /// Scroll speed while a drag rests near an edge.
+    autoscroll: Cell<(f64, f64)>,
+    /// Time of the last autoscroll frame, set while the tick callback runs.
+    frame_time: Cell<Option<i64>>,
+}
+
+impl Default for ExCanvasView {
+    fn default() -> Self {
+        Self {
+            canvas: RefCell::default(),
+            hadjustment: RefCell::default(),
+            vadjustment: RefCell::default(),
+            hadjustment_handler: RefCell::default(),
+            vadjustment_handler: RefCell::default(),
+            hscroll_policy: Cell::new(ScrollablePolicy::Minimum),
+            vscroll_policy: Cell::new(ScrollablePolicy::Minimum),
+            zoom: Cell::new(1.),
+            anchor: Cell::new(None),
+            pointer: Cell::new((0., 0.)),
+            space: Cell::new(false),
+            pan_start: Cell::new((0., 0.)),
+            pinch_start: Cell::new(1.),
+            autoscroll: Cell::new((0., 0.)),
+            frame_time: Cell::new(None),
+        }
+    }
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for ExCanvasView {
+    const NAME: &'static str = "CanvasView";
+    type Type = CanvasView;
+    type ParentType = gtk::Widget;
+    type Interfaces = (gtk::Scrollable,);
+
+    fn class_init(klass: &mut Self::Class) {
+        klass.set_css_name("canvasview");
+    }
+}
+
+impl ObjectImpl for ExCanvasView {
+    fn constructed(&self, obj: &Self::Type) {
+        self.parent_constructed(obj);
+        obj.set_overflow(Overflow::Hidden);
+        obj.set_focusable(false);
+
+        add_zoom_controllers(obj);
+        add_pan_gesture(obj);
+    }
+
+    fn dispose(&self, _obj: &Self::Type) {
+        if let Some(canvas) = self.canvas.borrow_mut().take() {
+            canvas.unparent();
+        }
+    }
+
+    fn properties() -> &'static [ParamSpec] {
+        static PROPERTIES: Lazy<Vec<ParamSpec>> = Lazy::new(|| {
+            let flags = ParamFlags::READWRITE | ParamFlags::EXPLICIT_NOTIFY;
+            vec![
+                ParamSpecDouble::new("zoom", "zoom", "zoom", MIN_ZOOM, MAX_ZOOM, 1., flags),
+                ParamSpecOverride::for_interface::<gtk::Scrollable>("hadjustment"),
+                ParamSpecOverride::for_interface::<gtk::Scrollable>("vadjustment"),
+                ParamSpecOverride::for_interface::<gtk::Scrollable>("hscroll-policy"),
+                ParamSpecOverride::for_interface::<gtk::Scrollable>("vscroll-policy"),
+            ]
+        });
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(&self, obj: &Self::Type, _id: usize, value: &Value, pspec: &ParamSpec) {
+        match pspec.name() {
+            "zoom" => obj.set_zoom(value.get().unwrap()),
+            "hadjustment" => obj.set_adjustment(Orientation::Horizontal, value.get().unwrap()),
+            "vadjustment" => obj.set_adjustment(Orientation::Vertical, value.get().unwrap()),
+            "hscroll-policy" => self.hscroll_policy.set(value.get().unwrap()),
+            "vscroll-policy" => self.vscroll_policy.set(value.get().unwrap()),
+            _ => unimplemented!(),
+        }
+    }
+
+    fn property(&self, _obj: &Self::Type, _id: usize, pspec: &ParamSpec) -> Value {
+        match pspec.name() {
+            "zoom" => self.zoom.get().to_value(),
+            "hadjustment" => self.hadjustment.borrow().to_value(),
+            "vadjustment" => self.vadjustment.borrow().to_value(),
+            "hscroll-policy" => self.hscroll_policy.get().to_value(),
+            "vscroll-policy" => self.vscroll_policy.get().to_value(),
+            _ => unimplemented!(),
+        }
+    }
+}
+
+impl WidgetImpl for ExCanvasView {
+    fn measure(
+        &self,
+        _widget: &Self::Type,
+        orientation: Orientation,
+        _for_size: i32,
+    ) -> (i32, i32, i32, i32) {
+        let canvas = self.canvas.borrow();
+        let bounds = canvas.as_ref().and_then(Canvas::items_bounds);
+        let size = bounds.map_or(0., |bounds| match orientation {
+            Orientation::Horizontal => bounds.x() + bounds.width(),
+            _ => bounds.y() + bounds.height(),
+        });
+        let natural = (size.max(0.) as f64 * self.zoom.get()).ceil() as i32;
+        (0, natural, -1, -1)
+    }
+
+    fn size_allocate(&self, widget: &Self::Type, width: i32, height: i32, _baseline: i32) {
+        let canvas = match self.canvas.borrow().clone() {
+            Some(canvas) => canvas,
+            None => return,
+        };
+        let zoom = self.zoom.get();
+        let (width, height) = (width as f64, height as f64);
+        let (left, top) = match self.anchor.take() {
+            Some(Anchor {
+                canvas: (x, y),
+                view: (view_x, view_y),
+            }) => (x * zoom - view_x, y * zoom - view_y),
+            None => widget.scroll_position(),
+        };
+
+        // Everything up to the items plus a margin can be scrolled to, and
+        // what is on screen stays reachable so the view never jumps while
+        // items move out of it.
+        let visible = Rect::new(
+            (left / zoom) as f32,
+            (top / zoom) as f32,
+            (width / zoom) as f32,
+            (height / zoom) as f32,
+        );
+        let area = canvas
+            .items_bounds()
+            .map_or_else(Rect::zero, |bounds| bounds.inset_r(-MARGIN, -MARGIN))
+            .union(&Rect::new(0., 0., 1., 1.))
+            .union(&visible);
+        for (adjustment, value, start, end, page) in [
+            (
+                &self.hadjustment,
+                left,
+                area.x(),
+                area.x() + area.width(),
+                width,
+            ),
+            (
+                &self.vadjustment,
+                top,
+                area.y(),
+                area.y() + area.height(),
+                height,
+            ),
+        ] {
+            if let Some(adjustment) = adjustment.borrow().as_ref() {
+                adjustment.configure(
+                    value,
+                    start as f64 * zoom,
+                    end as f64 * zoom,
+                    page * 0.1,
+                    page * 0.9,
+                    page,
+                );
+            }
+        }
+        canvas.set_visible_area(Some(visible));
+
+        // The canvas keeps its own coordinates; the transform maps them to
+        // the view. Items left of or above the origin lie outside its
+        // allocation, which `Canvas` allows while it is inside a view.
+        let min_width = canvas.measure(Orientation::Horizontal, -1).0;
+        let min_height = canvas.measure(Orientation::Vertical, -1).0;
+        let canvas_width = ((area.x() + area.width()).ceil() as i32).max(min_width);
+        let canvas_height = ((area.y() + area.height()).ceil() as i32).max(min_height);
+        let transform = gsk::Transform::new()
+            .translate(&Point::new(-left as f32, -top as f32))
+            .unwrap()
+            .scale(zoom as f32, zoom as f32)
+            .unwrap();
+        canvas.allocate(canvas_width, canvas_height, -1, Some(&transform));
+    }
+}
+
+impl ScrollableImpl for ExCanvasView {}
+
+fn add_zoom_controllers(view: &CanvasView) {
+    let motion = EventControllerMotion::new();
+    motion.connect_motion(clone!(@weak view => move |_, x, y| {
+        view.imp().pointer.set((x, y));
+    }));
+    view.add_controller(&motion);
+
+    let scroll = EventControllerScroll::new(EventControllerScrollFlags::VERTICAL);
+    scroll.connect_scroll(
+        clone!(@weak view => @default-return glib::signal::Inhibit(false), move |scroll, _, dy| {
+            if !scroll.current_event_state().contains(ModifierType::CONTROL_MASK) {
+                return glib::signal::Inhibit(false);
+            }
+            let (x, y) = view.imp().pointer.get();
+            view.zoom_at(view.zoom() * ZOOM_STEP.powf(-dy), x, y);
+            glib::signal::Inhibit(true)
+        }),
+    );
+    view.add_controller(&scroll);
+
+    let pinch = GestureZoom::new();
+    pinch.connect_begin(clone!(@weak view => move |_, _| {
+        view.imp().pinch_start.set(view.zoom());
+    }));
+    pinch.connect_scale_changed(clone!(@weak view => move |pinch, scale| {
+        let (x, y) = pinch.bounding_box_center().unwrap_or(view.imp().pointer.get());
+        view.zoom_at(view.imp().pinch_start.get() * scale, x, y);
+    }));
+    view.add_controller(&pinch);
+}
+
+/// Pans with the middle button, or with any button while Space is held.
+fn add_pan_gesture(view: &CanvasView) {
+    let keys = EventControllerKey::new();
+    keys.connect_key_pressed(
+        clone!(@weak view => @default-return glib::signal::Inhibit(false), move |_, key, _, _| {
+            if key != Key::space {
+                return glib::signal::Inhibit(false);
+            }
+            if !view.imp().space.replace(true) {
+                view.set_cursor_from_name(Some("grab"));
+            }
+            glib::signal::Inhibit(true)
+        }),
+    );
+    keys.connect_key_released(clone!(@weak view => move |_, key, _, _| {
+        if key == Key::space && view.imp().space.replace(false) {
+            view.set_cursor(None);
+        }
+    }));
+    view.add_controller(&keys);
+
+    let gesture = GestureDrag::new();
+    gesture.set_button(0);
+    gesture.set_propagation_phase(PropagationPhase::Capture);
+    gesture.connect_drag_begin(clone!(@weak view => move |gesture, _, _| {
+        let imp = view.imp();
+        if gesture.current_button() != BUTTON_MIDDLE && !imp.space.get() {
+            gesture.set_state(EventSequenceState::Denied);
+            return;
+        }
+        gesture.set_state(EventSequenceState::Claimed);
+        imp.pan_start.set(view.scroll_position());
+        view.set_cursor_from_name(Some("grabbing"));
+    }));
+    gesture.connect_drag_update(clone!(@weak view => move |_, dx, dy| {
+        let (left, top) = view.imp().pan_start.get();
+        view.scroll_to(left - dx, top - dy);
+    }));
+    gesture.connect_drag_end(clone!(@weak view => move |_, _, _| {
+        let cursor = view.imp().space.get().then_some("grab");
+        view.set_cursor_from_name(cursor);
+    }));
+    view.add_controller(&gesture);
+}